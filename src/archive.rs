@@ -0,0 +1,210 @@
+//! Transparent access to compressed files and archive members.
+//!
+//! Decompressed content is addressed as `<archive path>!/<member>`, e.g.
+//! `fixtures/data.zip!/inner/file.json`. Single-stream compressed files expose
+//! one member named after the file without its compression extension, e.g.
+//! `logs/app.log.gz!/app.log`.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use xz2::read::XzDecoder;
+
+/// Separator between the archive path and the member path.
+pub const MEMBER_SEPARATOR: &str = "!/";
+
+/// Upper bound on the decompressed size of a single member, to guard
+/// against decompression bombs.
+const MAX_MEMBER_BYTES: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Xz,
+    Bzip2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArchiveKind {
+    /// A single compressed stream (e.g. `app.log.gz`).
+    Stream(Compression),
+    /// A tarball, optionally wrapped in a compressed stream.
+    Tar(Compression),
+    Zip,
+}
+
+fn kind_for(path: &Path) -> Option<ArchiveKind> {
+    let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+
+    let tar = [
+        (".tar", Compression::None),
+        (".tar.gz", Compression::Gzip),
+        (".tgz", Compression::Gzip),
+        (".tar.zst", Compression::Zstd),
+        (".tzst", Compression::Zstd),
+        (".tar.xz", Compression::Xz),
+        (".txz", Compression::Xz),
+        (".tar.bz2", Compression::Bzip2),
+        (".tbz2", Compression::Bzip2),
+    ];
+    if let Some((_, c)) = tar.iter().find(|(ext, _)| name.ends_with(ext)) {
+        return Some(ArchiveKind::Tar(*c));
+    }
+
+    if name.ends_with(".zip") {
+        return Some(ArchiveKind::Zip);
+    }
+
+    let stream = [
+        (".gz", Compression::Gzip),
+        (".zst", Compression::Zstd),
+        (".xz", Compression::Xz),
+        (".bz2", Compression::Bzip2),
+    ];
+    stream
+        .iter()
+        .find(|(ext, _)| name.ends_with(ext))
+        .map(|(_, c)| ArchiveKind::Stream(*c))
+}
+
+/// Whether `path` looks like a compressed file or archive we can open.
+pub fn is_archive(path: &Path) -> bool {
+    kind_for(path).is_some()
+}
+
+/// Every way to split `archive!/member` into its two halves, leftmost
+/// separator first. Which one (if any) names an archive depends on the file
+/// system, since plain paths may contain `!/` too.
+pub fn member_path_splits(path: &str) -> impl Iterator<Item = (&str, &str)> {
+    path.match_indices(MEMBER_SEPARATOR)
+        .map(move |(at, sep)| (&path[..at], &path[at + sep.len()..]))
+}
+
+fn decoder<'a>(compression: Compression, file: File) -> io::Result<Box<dyn Read + 'a>> {
+    let reader = BufReader::new(file);
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => Box::new(MultiGzDecoder::new(reader)),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::with_buffer(reader)?),
+        Compression::Xz => Box::new(XzDecoder::new_multi_decoder(reader)),
+        Compression::Bzip2 => Box::new(MultiBzDecoder::new(reader)),
+    })
+}
+
+fn read_limited(reader: impl Read) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    reader.take(MAX_MEMBER_BYTES + 1).read_to_end(&mut buf)?;
+    if buf.len() as u64 > MAX_MEMBER_BYTES {
+        return Err(io::Error::other(format!(
+            "decompressed member exceeds {MAX_MEMBER_BYTES} bytes"
+        )));
+    }
+    Ok(buf)
+}
+
+/// Member name for a single-stream compressed file: the file name without
+/// its compression extension.
+fn stream_member_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Call `visit(member, content)` for every regular file inside the archive
+/// at `path`, until it returns `false`. Members that fail to decompress are
+/// skipped.
+pub fn for_each_member(path: &Path, visit: &mut dyn FnMut(&str, &[u8]) -> bool) -> io::Result<()> {
+    let Some(kind) = kind_for(path) else {
+        return Err(io::Error::other("not a supported archive"));
+    };
+
+    match kind {
+        ArchiveKind::Stream(compression) => {
+            let content = read_limited(decoder(compression, File::open(path)?)?)?;
+            visit(&stream_member_name(path), &content);
+        }
+        ArchiveKind::Tar(compression) => {
+            let mut archive = tar::Archive::new(decoder(compression, File::open(path)?)?);
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                if !entry.header().entry_type().is_file() {
+                    continue;
+                }
+                let name = entry.path()?.to_string_lossy().into_owned();
+                let content = match read_limited(&mut entry) {
+                    Ok(c) => c,
+                    Err(err) => {
+                        eprintln!("archive: skip member {name} in {}: {err}", path.display());
+                        continue;
+                    }
+                };
+                if !visit(&name, &content) {
+                    break;
+                }
+            }
+        }
+        ArchiveKind::Zip => {
+            let mut archive = zip::ZipArchive::new(BufReader::new(File::open(path)?))
+                .map_err(io::Error::other)?;
+            for index in 0..archive.len() {
+                let mut member = match archive.by_index(index) {
+                    Ok(m) => m,
+                    Err(err) => {
+                        eprintln!("archive: skip member #{index} in {}: {err}", path.display());
+                        continue;
+                    }
+                };
+                if member.is_dir() {
+                    continue;
+                }
+                let name = match member.name() {
+                    Ok(n) => n.into_owned(),
+                    Err(_) => continue,
+                };
+                let content = match read_limited(&mut member) {
+                    Ok(c) => c,
+                    Err(err) => {
+                        eprintln!("archive: skip member {name} in {}: {err}", path.display());
+                        continue;
+                    }
+                };
+                if !visit(&name, &content) {
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Read a single member of the archive at `path` fully into memory.
+pub fn read_member(path: &Path, member: &str) -> io::Result<Vec<u8>> {
+    let member = member.trim_start_matches('/');
+
+    if let Some(ArchiveKind::Zip) = kind_for(path) {
+        let mut archive =
+            zip::ZipArchive::new(BufReader::new(File::open(path)?)).map_err(io::Error::other)?;
+        let file = archive.by_name(member).map_err(|err| match err {
+            zip::result::ZipError::FileNotFound => io::Error::from(io::ErrorKind::NotFound),
+            other => io::Error::other(other),
+        })?;
+        return read_limited(file);
+    }
+
+    let mut found = None;
+    for_each_member(path, &mut |name, content| {
+        if name.trim_start_matches("./") == member {
+            found = Some(content.to_vec());
+            return false;
+        }
+        true
+    })?;
+
+    found.ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+}
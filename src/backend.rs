@@ -1,37 +1,544 @@
+use std::cell::Cell;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::fmt::Write as _;
 use std::fs::{File, OpenOptions};
-use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
-use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::SystemTime;
-
+use std::time::{Duration, Instant, SystemTime};
+
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use encoding_rs::Encoding;
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
 use globset::{Glob, GlobSet, GlobSetBuilder};
 use ignore::WalkBuilder;
+use ignore::types::{Types, TypesBuilder};
 use memmap2::Mmap;
-use regex::bytes::RegexBuilder as ByteRegexBuilder;
-
+use regex::bytes::{NoExpand, Regex as ByteRegex, RegexBuilder as ByteRegexBuilder};
+use similar::TextDiff;
+
+use crate::archive;
+use crate::command::{self, CommandSpec};
+use crate::compiler_output::{self, ColumnUnit};
+use crate::config::ServerConfig;
+use crate::content::{self, TextEncoding};
+use crate::elide::{self, ViewLine};
 use crate::error::{FsError, Result};
+use crate::format;
+use crate::lsp::{self, LspClient, LspClients};
+use crate::outline;
+use crate::rank::{self, FileInfo};
+use crate::stats;
+use crate::structural::{self, Pattern};
+use crate::symbol_index::SymbolIndex;
+use crate::syntax::{self, Language};
+use crate::tokens;
 use crate::types::{
-    CopyPathArgs, CopyPathResult, CreateFileArgs, CreateFileResult, DeletePathArgs,
-    DeletePathResult, FileChunkResult, FileEntry, FileRangeInfo, FindFileMatch, FindFilesArgs,
-    FindFilesResult, FindMatchMode, ListFilesArgs, ListFilesResult, MovePathArgs, MovePathResult,
-    OverwriteFileArgs, OverwriteFileResult, PathInfoArgs, PathInfoResult, RangeType, ReadFileArgs,
-    SearchHit, SearchMode, SearchTextArgs, SearchTextResult, StatArgs, StatResult,
+    ByteEncoding, CopyPathArgs, CopyPathResult, CreateFileArgs, CreateFileResult, DeletePathArgs,
+    DeletePathResult, Diagnostic, DiagnosticSeverity, DiagnosticsArgs, DiagnosticsFormat,
+    DiagnosticsResult, DirectoryDepth, FileChunkResult, FileEntry, FileOutlineArgs,
+    FileOutlineResult, FileRangeInfo, FileReplacement, FileSize, FileTypeInfo, FindFileMatch,
+    FindFilesArgs, FindFilesResult, FindMatchMode, FindReferencesArgs, FormatReport, HoverResult,
+    LanguageStats, LineRange, ListFileTypesArgs, ListFileTypesResult, ListFilesArgs,
+    ListFilesResult, LspLocation, LspLocationsResult, LspPositionArgs, MediaInfo, MovePathArgs,
+    MovePathResult, OverwriteFileArgs, OverwriteFileResult, PathInfoArgs, PathInfoResult,
+    RangeType, ReadFileArgs, ReadFilesArgs, ReadFilesItem, ReadFilesResult, RenameSymbolArgs,
+    RenameSymbolResult, ReplaceAction, ReplaceTextArgs, ReplaceTextResult, RepoStatsArgs,
+    RepoStatsResult, RunCommandArgs, RunCommandResult, SearchHit, SearchMode, SearchStructuralArgs,
+    SearchStructuralResult, SearchSymbolsArgs, SearchSymbolsResult, SearchTextArgs,
+    SearchTextResult, StatArgs, StatResult, SymbolMatch, SymbolMatchMode,
 };
+use crate::validate;
 
 const DEFAULT_MAX_SEARCH_RESULTS: u32 = 200;
 const DEFAULT_SEARCH_CONTEXT_LINES: u32 = 2;
+const DEFAULT_MAX_SYMBOL_RESULTS: u32 = 100;
+const DEFAULT_MAX_REFERENCES: u32 = 200;
+const DEFAULT_DIAGNOSTICS_WAIT_MS: u64 = 5000;
+const DEFAULT_COMMAND_TIMEOUT_MS: u64 = 120_000;
+const DEFAULT_MAX_COMMAND_TIMEOUT_MS: u64 = 600_000;
+const DEFAULT_MAX_COMMAND_OUTPUT: u64 = 64 * 1024;
+const DEFAULT_MAX_COMMAND_OUTPUT_LIMIT: u64 = 1024 * 1024;
 const DEFAULT_MAX_READ_BYTES: u64 = 64 * 1024;
 const DEFAULT_MAX_READ_LINES: u64 = 200;
+const DEFAULT_BATCH_MAX_BYTES: u64 = 256 * 1024;
+/// Bytes read per requested token when `max_tokens` replaces `max_bytes`;
+/// generous so the token budget, not the byte window, is what binds.
+const BYTES_PER_TOKEN_WINDOW: u64 = 16;
 const DEFAULT_LIST_MAX_RESULTS: u32 = 500;
+const MAX_RANK_CANDIDATES: u32 = 10_000;
+const DEFAULT_REPLACE_MAX_FILES: u32 = 200;
+const DEFAULT_STATS_TOP: u32 = 10;
+const DIFF_CONTEXT_LINES: usize = 3;
+const MCP_IGNORE_FILENAME: &str = ".mcpignore";
+/// Globs added to the `ignore` crate's built-in file types: project files
+/// that belong to a language without sharing its extension.
+const EXTRA_TYPE_GLOBS: &[(&str, &str)] = &[("rust", "Cargo.toml"), ("rust", "Cargo.lock")];
+const BINARY_MATCH_TEXT: &str = "binary file matches";
+/// Largest image returned as MCP image content (after any downscaling).
+const MAX_IMAGE_BYTES: u64 = 5 * 1024 * 1024;
+/// Largest PDF returned as an embedded resource.
+const MAX_PDF_BYTES: u64 = 10 * 1024 * 1024;
+/// Largest image file loaded at all, even when it will be downscaled.
+const MAX_IMAGE_SOURCE_BYTES: u64 = 64 * 1024 * 1024;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+const STOP_CHECK_LINES: usize = 4096;
+
+/// A file rewrite computed by `replace_text`, `search_structural` or
+/// `rename_symbol`, written or previewed by `LocalGitAwareFs::finish_rewrites`.
+struct PendingRewrite {
+    path: PathBuf,
+    original: String,
+    replaced: String,
+    /// `replaced` in the file's original encoding.
+    encoded: Vec<u8>,
+    count: u64,
+}
+
+/// New content for a file, written to a sibling temporary file that
+/// `commit` renames over the target, so readers never observe a partially
+/// written file. A symlink is written through: its target is replaced and
+/// the link kept.
+struct StagedWrite {
+    target: PathBuf,
+    tmp_path: PathBuf,
+}
+
+impl StagedWrite {
+    fn new(path: &Path, bytes: &[u8]) -> Result<Self> {
+        let write_error = |source| FsError::WriteFile {
+            path: path.to_path_buf(),
+            source,
+        };
+        let target = match std::fs::symlink_metadata(path) {
+            Ok(meta) if meta.file_type().is_symlink() => {
+                std::fs::canonicalize(path).map_err(write_error)?
+            }
+            _ => path.to_path_buf(),
+        };
+        let parent = target.parent().unwrap_or_else(|| Path::new("."));
+        let file_name = target
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        // Tools run concurrently, so the name is unique per write, and
+        // `create_new` refuses to reuse a leftover file.
+        static NEXT_TMP: AtomicU64 = AtomicU64::new(0);
+        let tmp_path = parent.join(format!(
+            ".{file_name}.{}.{}.tmp",
+            std::process::id(),
+            NEXT_TMP.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let result = (|| -> io::Result<()> {
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&tmp_path)?;
+            file.write_all(bytes)?;
+            file.sync_all()?;
+            if let Ok(meta) = std::fs::metadata(&target) {
+                std::fs::set_permissions(&tmp_path, meta.permissions())?;
+            }
+            Ok(())
+        })();
+        match result {
+            Ok(()) => Ok(Self { target, tmp_path }),
+            Err(source) => {
+                let _ = std::fs::remove_file(&tmp_path);
+                Err(write_error(source))
+            }
+        }
+    }
+
+    fn commit(self) -> Result<()> {
+        std::fs::rename(&self.tmp_path, &self.target).map_err(|source| {
+            self.discard();
+            FsError::WriteFile {
+                path: self.target.clone(),
+                source,
+            }
+        })
+    }
+
+    fn discard(&self) {
+        let _ = std::fs::remove_file(&self.tmp_path);
+    }
+}
+
+/// Walker settings shared by the gitignore-aware tools (`list_files`,
+/// `find_files`, `search_text`, `replace_text`).
+struct WalkOptions {
+    include_hidden: bool,
+    no_ignore: bool,
+    no_ignore_vcs: bool,
+    follow_symlinks: bool,
+    max_depth: Option<usize>,
+    types: Option<Types>,
+}
+
+impl WalkOptions {
+    /// Build options from a tool's optional walk arguments. A `max_depth`
+    /// of 0 would only yield the start directory itself, so it is raised
+    /// to 1.
+    fn new(
+        include_hidden: Option<bool>,
+        no_ignore: Option<bool>,
+        no_ignore_vcs: Option<bool>,
+        follow_symlinks: Option<bool>,
+        max_depth: Option<u32>,
+    ) -> Self {
+        Self {
+            include_hidden: include_hidden.unwrap_or(false),
+            no_ignore: no_ignore.unwrap_or(false),
+            no_ignore_vcs: no_ignore_vcs.unwrap_or(false),
+            follow_symlinks: follow_symlinks.unwrap_or(false),
+            max_depth: max_depth.map(|d| d.max(1) as usize),
+            types: None,
+        }
+    }
+
+    /// Restrict the walk to the given file types.
+    fn with_types(mut self, types: Option<Types>) -> Self {
+        self.types = types;
+        self
+    }
+
+    /// Cap the walk at one level unless `recursive` is set.
+    fn recursive(mut self, recursive: bool) -> Self {
+        if !recursive {
+            self.max_depth = Some(1);
+        }
+        self
+    }
+}
+
+/// Progress snapshot reported while a walk is running.
+pub struct WalkProgress {
+    pub entries_scanned: u64,
+    pub matches: u64,
+    /// Text to report instead of the entry and match counts (e.g. the
+    /// latest output of `run_command`).
+    pub message: Option<String>,
+}
+
+type ProgressFn = dyn Fn(WalkProgress) + Send + Sync;
+
+/// Cooperative stop signal, deadline and progress sink for the long-running
+/// walks in `list_files`, `find_files` and `search_text`.
+#[derive(Clone, Default)]
+pub struct WalkControl {
+    cancelled: Arc<AtomicBool>,
+    timed_out: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+    scanned: Arc<AtomicU64>,
+    last_report: Arc<Mutex<Option<Instant>>>,
+    progress: Option<Arc<ProgressFn>>,
+}
+
+impl WalkControl {
+    pub fn new(timeout: Option<Duration>) -> Self {
+        Self {
+            deadline: timeout.map(|t| Instant::now() + t),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_progress(
+        mut self,
+        progress: impl Fn(WalkProgress) + Send + Sync + 'static,
+    ) -> Self {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+
+    /// Flag that, once set, makes the walk stop at the next entry.
+    pub fn cancel_flag(&self) -> Arc<AtomicBool> {
+        self.cancelled.clone()
+    }
+
+    fn should_stop(&self) -> bool {
+        if self.cancelled.load(Ordering::Relaxed) || self.timed_out.load(Ordering::Relaxed) {
+            return true;
+        }
+        if let Some(deadline) = self.deadline
+            && Instant::now() >= deadline
+        {
+            self.timed_out.store(true, Ordering::Relaxed);
+            return true;
+        }
+        false
+    }
+
+    fn timed_out(&self) -> bool {
+        self.timed_out.load(Ordering::Relaxed)
+    }
+
+    /// Count one scanned entry and report progress at most once per
+    /// `PROGRESS_INTERVAL`.
+    fn tick(&self, matches: u64) {
+        let scanned = self.scanned.fetch_add(1, Ordering::Relaxed) + 1;
+        let Some(progress) = &self.progress else {
+            return;
+        };
+
+        // Never block walker threads on the progress lock.
+        let Ok(mut last) = self.last_report.try_lock() else {
+            return;
+        };
+        let now = Instant::now();
+        if last.is_some_and(|t| now.duration_since(t) < PROGRESS_INTERVAL) {
+            return;
+        }
+        *last = Some(now);
+        drop(last);
+
+        progress(WalkProgress {
+            entries_scanned: scanned,
+            matches,
+            message: None,
+        });
+    }
+
+    /// Report progress unthrottled, e.g. each batch of command output.
+    fn report(&self, progress: WalkProgress) {
+        if let Some(report) = &self.progress {
+            report(progress);
+        }
+    }
+}
+
+/// Shared state for one `search_text` call, used from every walker thread.
+struct SearchState {
+    matcher: ByteRegex,
+    context_lines: usize,
+    skip: u32,
+    max_results: u32,
+    search_binary: bool,
+    hits: Mutex<Vec<SearchHit>>,
+    /// Line count of every file with a match, for ranking.
+    line_counts: Mutex<HashMap<String, u64>>,
+    // Global counters across all threads: how many matches have been
+    // seen (for `skip`) and whether we hit the `max_results` cap.
+    seen_matches: AtomicU32,
+    hit_limit: AtomicBool,
+    control: WalkControl,
+}
+
+impl SearchState {
+    fn is_done(&self) -> bool {
+        self.hit_limit.load(Ordering::Relaxed)
+    }
+
+    /// Whether the walk should stop: result cap reached, request cancelled
+    /// or timed out.
+    fn should_stop(&self) -> bool {
+        self.is_done() || self.control.should_stop()
+    }
+
+    /// Count one match and record it unless it falls inside the skipped
+    /// prefix. Returns `false` once `max_results` is reached.
+    fn offer(&self, make_hit: impl FnOnce() -> SearchHit) -> bool {
+        let seen_after = self.seen_matches.fetch_add(1, Ordering::Relaxed) + 1;
+        if seen_after <= self.skip {
+            return true;
+        }
+
+        let mut guard = self.hits.lock().expect("search_text: hits mutex poisoned");
+        if guard.len() as u32 >= self.max_results {
+            self.hit_limit.store(true, Ordering::Relaxed);
+            return false;
+        }
+
+        guard.push(make_hit());
+
+        if guard.len() as u32 >= self.max_results {
+            self.hit_limit.store(true, Ordering::Relaxed);
+            return false;
+        }
+        true
+    }
+
+    fn record_line_count(&self, display_path: &str, line_count: u64) {
+        self.line_counts
+            .lock()
+            .expect("search_text: line counts mutex poisoned")
+            .insert(display_path.to_string(), line_count);
+    }
+
+    /// Search the content of one file (or archive member). Binary content
+    /// is skipped or reported once; text in a legacy encoding is transcoded
+    /// to UTF-8 first. Returns `false` when the search should stop.
+    fn search_content(&self, buf: &[u8], display_path: &str) -> bool {
+        if content::is_binary(buf) {
+            return !self.search_binary || self.search_binary(buf, display_path);
+        }
+
+        let encoding = content::detect_encoding(&buf[..buf.len().min(content::ENCODING_SNIFF_LEN)]);
+        if encoding.is_utf8() {
+            self.search_lines(buf, display_path, None)
+        } else {
+            let text = encoding.decode(buf);
+            self.search_lines(text.as_bytes(), display_path, Some(encoding.name()))
+        }
+    }
+
+    /// Search `buf` line by line, keeping at most one hit per line.
+    /// Returns `false` when the search should stop.
+    fn search_lines(&self, buf: &[u8], display_path: &str, encoding: Option<&str>) -> bool {
+        // Precompute line start offsets (0-based byte indices).
+        let mut line_starts: Vec<usize> = Vec::new();
+        line_starts.push(0);
+        for (i, &b) in buf.iter().enumerate() {
+            if b == b'\n' && i + 1 < buf.len() {
+                line_starts.push(i + 1);
+            }
+        }
+
+        let line_count = line_starts.len();
+        let mut recorded = false;
+        let line_range = |idx: usize| {
+            let start = line_starts[idx];
+            let end = if idx + 1 < line_count {
+                line_starts[idx + 1].saturating_sub(1)
+            } else {
+                buf.len()
+            };
+            (start, end)
+        };
+
+        for idx in 0..line_count {
+            if self.is_done() || (idx % STOP_CHECK_LINES == 0 && self.control.should_stop()) {
+                return false;
+            }
+
+            let (line_start, line_end) = line_range(idx);
+            if line_start >= line_end || line_end > buf.len() {
+                continue;
+            }
+
+            let line_slice = &buf[line_start..line_end];
+
+            let mat = match self.matcher.find(line_slice) {
+                Some(m) => m,
+                None => continue,
+            };
+            if !recorded {
+                self.record_line_count(display_path, line_count as u64);
+                recorded = true;
+            }
+
+            let keep_going = self.offer(|| {
+                let start_ctx = idx.saturating_sub(self.context_lines);
+                let end_ctx = usize::min(line_count, idx + 1 + self.context_lines);
+
+                let mut context_before = Vec::new();
+                let mut context_after = Vec::new();
+
+                for ctx_idx in start_ctx..end_ctx {
+                    if ctx_idx == idx {
+                        continue;
+                    }
+
+                    let (ctx_start, ctx_end) = line_range(ctx_idx);
+                    if ctx_start >= ctx_end || ctx_end > buf.len() {
+                        continue;
+                    }
+
+                    let ctx_text = String::from_utf8_lossy(&buf[ctx_start..ctx_end]).to_string();
+
+                    if ctx_idx < idx {
+                        context_before.push(ctx_text);
+                    } else {
+                        context_after.push(ctx_text);
+                    }
+                }
+
+                SearchHit {
+                    path: display_path.to_string(),
+                    line: idx as u64 + 1,
+                    column: mat.start() as u64,
+                    line_text: String::from_utf8_lossy(line_slice).to_string(),
+                    context_before,
+                    context_after,
+                    binary: false,
+                    encoding: encoding.map(str::to_string),
+                    score: None,
+                    end_line: None,
+                    captures: None,
+                }
+            });
+
+            if !keep_going {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Report a single "binary file matches" hit for `buf` when the matcher
+    /// finds anything in it, instead of lossy line text.
+    fn search_binary(&self, buf: &[u8], display_path: &str) -> bool {
+        let Some(mat) = self.matcher.find(buf) else {
+            return true;
+        };
+
+        let line_start = buf[..mat.start()]
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |i| i + 1);
+        let line = buf[..line_start].iter().filter(|&&b| b == b'\n').count() as u64 + 1;
+        let line_count = line + buf[line_start..].iter().filter(|&&b| b == b'\n').count() as u64;
+        self.record_line_count(display_path, line_count);
+
+        self.offer(|| SearchHit {
+            path: display_path.to_string(),
+            line,
+            column: (mat.start() - line_start) as u64,
+            line_text: BINARY_MATCH_TEXT.to_string(),
+            context_before: Vec::new(),
+            context_after: Vec::new(),
+            binary: true,
+            encoding: None,
+            score: None,
+            end_line: None,
+            captures: None,
+        })
+    }
+}
+
+/// An image or PDF read by `read_file`, to be returned as MCP media content.
+pub struct MediaContent {
+    pub info: MediaInfo,
+    /// `file://` URI of the file, used for embedded resources.
+    pub uri: String,
+    pub data: Vec<u8>,
+}
+
+/// Query matcher for `find_files`.
+enum FindMatcher {
+    /// Case-(in)sensitive literal substring, compiled to a regex.
+    Substring(regex::Regex),
+    /// fzf/skim-style subsequence scoring against the relative path.
+    Fuzzy(Box<SkimMatcherV2>, String),
+}
 
 #[derive(Clone)]
 pub struct LocalGitAwareFs {
     root: PathBuf,
+    config: ServerConfig,
+    symbol_index: Arc<SymbolIndex>,
+    lsp: Arc<LspClients>,
 }
 
 impl LocalGitAwareFs {
-    pub fn new(root: PathBuf) -> Result<Self> {
+    pub fn new(root: PathBuf, config: ServerConfig) -> Result<Self> {
         let root = if root.as_os_str().is_empty() {
             PathBuf::from(".")
         } else {
@@ -48,7 +555,22 @@ impl LocalGitAwareFs {
             return Err(FsError::RootNotDirectory { path: root });
         }
 
-        Ok(Self { root })
+        let lsp = Arc::new(LspClients::new(
+            root.clone(),
+            config.language_servers.clone(),
+        ));
+        let fs = Self {
+            root,
+            config,
+            symbol_index: Arc::default(),
+            lsp,
+        };
+
+        // Surface invalid custom file type definitions at startup rather
+        // than on the first filtered call.
+        fs.types_builder()?;
+
+        Ok(fs)
     }
 
     fn resolve_path(&self, rel: &str) -> Result<PathBuf> {
@@ -114,6 +636,138 @@ impl LocalGitAwareFs {
         }
     }
 
+    /// Start from the built-in `ignore` file type definitions and add any
+    /// custom definitions from the server config.
+    fn types_builder(&self) -> Result<TypesBuilder> {
+        let mut builder = TypesBuilder::new();
+        builder.add_defaults();
+        for (name, glob) in EXTRA_TYPE_GLOBS {
+            builder
+                .add(name, glob)
+                .expect("built-in file type globs are valid");
+        }
+
+        for (name, globs) in &self.config.file_types {
+            for glob in globs {
+                builder
+                    .add(name, glob)
+                    .map_err(|source| FsError::InvalidFileTypeDef {
+                        name: name.clone(),
+                        glob: glob.clone(),
+                        source,
+                    })?;
+            }
+        }
+
+        Ok(builder)
+    }
+
+    fn build_types(
+        &self,
+        types: &Option<Vec<String>>,
+        exclude_types: &Option<Vec<String>>,
+    ) -> Result<Option<Types>> {
+        let selected = types.as_deref().unwrap_or_default();
+        let negated = exclude_types.as_deref().unwrap_or_default();
+
+        if selected.is_empty() && negated.is_empty() {
+            return Ok(None);
+        }
+
+        let mut builder = self.types_builder()?;
+        for name in selected {
+            builder.select(name);
+        }
+        for name in negated {
+            builder.negate(name);
+        }
+
+        builder
+            .build()
+            .map(Some)
+            .map_err(|source| FsError::InvalidFileType { source })
+    }
+
+    pub fn list_file_types(&self, args: ListFileTypesArgs) -> Result<ListFileTypesResult> {
+        let filter = args.filter.map(|f| f.to_lowercase());
+
+        let types = self
+            .types_builder()?
+            .definitions()
+            .into_iter()
+            .filter(|def| {
+                filter
+                    .as_deref()
+                    .is_none_or(|f| def.name().to_lowercase().contains(f))
+            })
+            .map(|def| FileTypeInfo {
+                name: def.name().to_string(),
+                globs: def.globs().to_vec(),
+            })
+            .collect();
+
+        Ok(ListFileTypesResult { types })
+    }
+
+    fn walk_builder(&self, start_path: &Path, opts: WalkOptions) -> WalkBuilder {
+        let mut builder = WalkBuilder::new(start_path);
+        builder
+            .standard_filters(true)
+            .hidden(!opts.include_hidden)
+            .follow_links(opts.follow_symlinks)
+            .max_depth(opts.max_depth);
+
+        if opts.no_ignore {
+            builder
+                .ignore(false)
+                .parents(false)
+                .git_ignore(false)
+                .git_global(false)
+                .git_exclude(false);
+        } else {
+            builder.add_custom_ignore_filename(MCP_IGNORE_FILENAME);
+            if opts.no_ignore_vcs {
+                builder
+                    .git_ignore(false)
+                    .git_global(false)
+                    .git_exclude(false);
+            }
+        }
+
+        // Hidden files are opt-in, but the git metadata directory is never
+        // useful to agents and would dominate every listing and search.
+        // Followed symlinks must not lead the walk out of the repository
+        // (or out of an absolute start directory outside it).
+        let skip_git = opts.include_hidden;
+        let boundary = opts.follow_symlinks.then(|| {
+            if start_path.starts_with(&self.root) {
+                self.root.clone()
+            } else {
+                start_path.to_path_buf()
+            }
+        });
+        if skip_git || boundary.is_some() {
+            builder.filter_entry(move |entry| {
+                if skip_git && entry.file_name() == ".git" {
+                    return false;
+                }
+                match &boundary {
+                    Some(boundary) if entry.path_is_symlink() => entry
+                        .path()
+                        .canonicalize()
+                        .is_ok_and(|target| target.starts_with(boundary)),
+                    _ => true,
+                }
+            });
+        }
+
+        if let Some(types) = opts.types {
+            builder.types(types);
+        }
+
+        builder
+    }
+
     fn find_git_root(start: &Path) -> Option<PathBuf> {
         let mut current = Some(start.to_path_buf());
         while let Some(dir) = current {
@@ -126,7 +780,11 @@ impl LocalGitAwareFs {
         None
     }
 
-    pub fn list_files(&self, args: ListFilesArgs) -> Result<ListFilesResult> {
+    pub fn list_files(
+        &self,
+        args: ListFilesArgs,
+        control: &WalkControl,
+    ) -> Result<ListFilesResult> {
         let root_arg = args.root.as_deref().unwrap_or(".");
         let root_path = Path::new(root_arg);
         let is_absolute = root_path.is_absolute();
@@ -179,17 +837,27 @@ impl LocalGitAwareFs {
 
         let include_globs: Option<GlobSet> = Self::build_globset(&args.include_globs)?;
         let exclude_globs: Option<GlobSet> = Self::build_globset(&args.exclude_globs)?;
-
-        let mut builder = WalkBuilder::new(&start_path);
-        builder.standard_filters(true);
-        if !recursive {
-            builder.max_depth(Some(1));
-        }
+        let walk = WalkOptions::new(
+            args.include_hidden,
+            args.no_ignore,
+            args.no_ignore_vcs,
+            args.follow_symlinks,
+            args.max_depth,
+        )
+        .recursive(recursive)
+        .with_types(self.build_types(&args.types, &args.exclude_types)?);
+
+        let builder = self.walk_builder(&start_path, walk);
 
         let mut entries = Vec::new();
         let mut seen: u32 = 0;
         let mut hit_limit = false;
         for result in builder.build() {
+            if control.should_stop() {
+                break;
+            }
+            control.tick(entries.len() as u64);
+
             let entry = match result {
                 Ok(e) => e,
                 Err(err) => {
@@ -260,10 +928,15 @@ impl LocalGitAwareFs {
         Ok(ListFilesResult {
             entries,
             has_more: hit_limit,
+            timed_out: control.timed_out(),
         })
     }
 
-    pub fn find_files(&self, args: FindFilesArgs) -> Result<FindFilesResult> {
+    pub fn find_files(
+        &self,
+        args: FindFilesArgs,
+        control: &WalkControl,
+    ) -> Result<FindFilesResult> {
         let root_arg = args.root.as_deref().unwrap_or(".");
         let root_path = Path::new(root_arg);
         let is_absolute = root_path.is_absolute();
@@ -317,6 +990,15 @@ impl LocalGitAwareFs {
 
         let include_globs: Option<GlobSet> = Self::build_globset(&args.include_globs)?;
         let exclude_globs: Option<GlobSet> = Self::build_globset(&args.exclude_globs)?;
+        let walk = WalkOptions::new(
+            args.include_hidden,
+            args.no_ignore,
+            args.no_ignore_vcs,
+            args.follow_symlinks,
+            args.max_depth,
+        )
+        .recursive(recursive)
+        .with_types(self.build_types(&args.types, &args.exclude_types)?);
 
         // How many matches we need to collect in total (for paging).
         let total_needed = skip.saturating_add(max_results);
@@ -325,6 +1007,7 @@ impl LocalGitAwareFs {
             return Ok(FindFilesResult {
                 matches: Vec::new(),
                 has_more: false,
+                timed_out: false,
             });
         }
 
@@ -338,24 +1021,38 @@ impl LocalGitAwareFs {
 
         let repo_root = self.root.clone();
 
-        // Use a regex matcher for literal substring search, to avoid
-        // allocating a lowercased string per entry in the hot loop.
         let query = args.query;
-        let escaped = regex::escape(&query);
-        let matcher = regex::RegexBuilder::new(&escaped)
-            .case_insensitive(!case_sensitive)
-            .build()
-            .map_err(|source| FsError::InvalidFindFilesRegex {
-                query: query.clone(),
-                source,
-            })?;
+        let matcher = if match_mode == FindMatchMode::Fuzzy {
+            let fuzzy = SkimMatcherV2::default();
+            let fuzzy = if case_sensitive {
+                fuzzy.respect_case()
+            } else {
+                fuzzy.ignore_case()
+            };
+            FindMatcher::Fuzzy(Box::new(fuzzy), query)
+        } else {
+            // Use a regex matcher for literal substring search, to avoid
+            // allocating a lowercased string per entry in the hot loop.
+            let escaped = regex::escape(&query);
+            let matcher = regex::RegexBuilder::new(&escaped)
+                .case_insensitive(!case_sensitive)
+                .build()
+                .map_err(|source| FsError::InvalidFindFilesRegex {
+                    query: query.clone(),
+                    source,
+                })?;
+            FindMatcher::Substring(matcher)
+        };
         let matcher = Arc::new(matcher);
 
-        let mut builder = WalkBuilder::new(&start_path);
-        builder.standard_filters(true);
-        if !recursive {
-            builder.max_depth(Some(1));
-        }
+        // Fuzzy results are ordered by score, so every candidate has to be
+        // seen before the best `total_needed` can be picked.
+        let collect_cap = match *matcher {
+            FindMatcher::Fuzzy(..) => u32::MAX,
+            FindMatcher::Substring(_) => total_needed,
+        };
+
+        let builder = self.walk_builder(&start_path, walk);
 
         builder.build_parallel().run(|| {
             let matches = matches.clone();
@@ -368,9 +1065,10 @@ impl LocalGitAwareFs {
             let matcher = matcher.clone();
 
             Box::new(move |entry_res| {
-                if hit_limit.load(Ordering::Relaxed) {
+                if hit_limit.load(Ordering::Relaxed) || control.should_stop() {
                     return ignore::WalkState::Quit;
                 }
+                control.tick(seen_matches.load(Ordering::Relaxed) as u64);
 
                 let entry = match entry_res {
                     Ok(e) => e,
@@ -414,15 +1112,29 @@ impl LocalGitAwareFs {
                         Some(name) => name,
                         None => return ignore::WalkState::Continue,
                     },
-                    FindMatchMode::Path => rel_str.as_ref(),
+                    FindMatchMode::Path | FindMatchMode::Fuzzy => rel_str.as_ref(),
                 };
 
-                if !matcher.is_match(haystack) {
-                    return ignore::WalkState::Continue;
-                }
+                let (score, positions) = match &*matcher {
+                    FindMatcher::Substring(re) => {
+                        if !re.is_match(haystack) {
+                            return ignore::WalkState::Continue;
+                        }
+                        (None, None)
+                    }
+                    FindMatcher::Fuzzy(fuzzy, query) => {
+                        match fuzzy.fuzzy_indices(haystack, query) {
+                            Some((score, indices)) => (
+                                Some(score),
+                                Some(indices.into_iter().map(|i| i as u32).collect()),
+                            ),
+                            None => return ignore::WalkState::Continue,
+                        }
+                    }
+                };
 
                 let seen_after = seen_matches.fetch_add(1, Ordering::Relaxed) + 1;
-                if seen_after > total_needed {
+                if seen_after > collect_cap {
                     hit_limit.store(true, Ordering::Relaxed);
                     return ignore::WalkState::Quit;
                 }
@@ -433,6 +1145,8 @@ impl LocalGitAwareFs {
                 guard.push(FindFileMatch {
                     path: rel_owned,
                     is_dir,
+                    score,
+                    positions,
                 });
 
                 ignore::WalkState::Continue
@@ -446,9 +1160,18 @@ impl LocalGitAwareFs {
             std::mem::take(&mut *guard)
         };
 
-        // Parallel walking does not guarantee order; sort by path to make
-        // results deterministic before applying skip/limit.
-        matches.sort_by(|a, b| a.path.cmp(&b.path));
+        // Parallel walking does not guarantee order; sort by path (or by
+        // descending fuzzy score, then shorter path) to make results
+        // deterministic before applying skip/limit.
+        matches.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then_with(|| match a.score {
+                    Some(_) => a.path.len().cmp(&b.path.len()),
+                    None => std::cmp::Ordering::Equal,
+                })
+                .then_with(|| a.path.cmp(&b.path))
+        });
 
         let skip_usize = skip as usize;
         let max_usize = max_results as usize;
@@ -470,6 +1193,7 @@ impl LocalGitAwareFs {
         Ok(FindFilesResult {
             matches: sliced,
             has_more,
+            timed_out: control.timed_out(),
         })
     }
 
@@ -625,6 +1349,21 @@ impl LocalGitAwareFs {
             self.root.join(path)
         };
 
+        // Format and check the content before touching the file system, so
+        // rejected content does not leave new parent directories behind.
+        let (content, formatting) = match args.content {
+            Some(content) => {
+                let (content, formatting) = self.format_for_write(&resolved, content, args.format);
+                (Some(content), formatting)
+            }
+            None => (None, None),
+        };
+        if args.validate_syntax.unwrap_or(false)
+            && let Some(content) = &content
+        {
+            Self::validate_syntax(&resolved, content)?;
+        }
+
         // Create parent directories if requested.
         if create_parents
             && let Some(parent) = resolved.parent()
@@ -674,6 +1413,12 @@ impl LocalGitAwareFs {
             }
         }
 
+        let content = match (&content, &existed_meta) {
+            (Some(content), Some(_)) => Self::encode_like_existing(&resolved, content)?,
+            (Some(content), None) => content.as_bytes().to_vec(),
+            (None, _) => Vec::new(),
+        };
+
         // Open and write content.
         let mut file = OpenOptions::new()
             .write(true)
@@ -685,14 +1430,11 @@ impl LocalGitAwareFs {
                 source,
             })?;
 
-        if let Some(content) = args.content {
-            use std::io::Write;
-            file.write_all(content.as_bytes())
-                .map_err(|source| FsError::WriteFile {
-                    path: resolved.clone(),
-                    source,
-                })?;
-        }
+        file.write_all(&content)
+            .map_err(|source| FsError::WriteFile {
+                path: resolved.clone(),
+                source,
+            })?;
 
         let canonical = resolved.canonicalize().unwrap_or_else(|_| resolved.clone());
 
@@ -704,6 +1446,7 @@ impl LocalGitAwareFs {
             path: display_path,
             created: existed_meta.is_none(),
             overwritten: existed_meta.is_some(),
+            formatting,
         })
     }
 
@@ -1093,6 +1836,12 @@ impl LocalGitAwareFs {
             }
         }
 
+        let (content, formatting) = self.format_for_write(&resolved, args.content, args.format);
+        if args.validate_syntax.unwrap_or(false) {
+            Self::validate_syntax(&resolved, &content)?;
+        }
+        let content = Self::encode_like_existing(&resolved, &content)?;
+
         let mut file = OpenOptions::new()
             .write(true)
             .truncate(true)
@@ -1102,8 +1851,7 @@ impl LocalGitAwareFs {
                 source,
             })?;
 
-        use std::io::Write;
-        file.write_all(args.content.as_bytes())
+        file.write_all(&content)
             .map_err(|source| FsError::WriteFile {
                 path: resolved.clone(),
                 source,
@@ -1115,56 +1863,565 @@ impl LocalGitAwareFs {
             .strip_root(&canonical)
             .unwrap_or_else(|| canonical.display().to_string());
 
-        Ok(OverwriteFileResult { path: display_path })
+        Ok(OverwriteFileResult {
+            path: display_path,
+            formatting,
+        })
     }
 
-    pub fn read_file(&self, args: ReadFileArgs) -> Result<FileChunkResult> {
-        let abs_path = self.resolve_path(&args.path)?;
-        let meta = std::fs::metadata(&abs_path).map_err(|source| FsError::FileMetadata {
-            path: abs_path.to_path_buf(),
-            source,
-        })?;
-        if !meta.is_file() {
-            return Err(FsError::ReadFileNotFile {
-                path: abs_path.to_path_buf(),
-            });
-        }
-        let has_byte_params = args.offset_bytes.is_some() || args.max_bytes.is_some();
-        let has_line_params = args.start_line.is_some() || args.max_lines.is_some();
+    /// Refuse to write `content` to `path` if it does not parse.
+    fn validate_syntax(path: &Path, content: &str) -> Result<()> {
+        validate::check(path, content).map_err(|invalid| FsError::InvalidSyntax {
+            path: path.to_path_buf(),
+            language: invalid.language.to_string(),
+            errors: invalid.to_string(),
+        })
+    }
 
-        let range_type = match args.range_type {
-            Some(rt) => rt,
-            None if has_line_params => RangeType::Lines,
-            None => RangeType::Bytes,
+    /// Run the formatter for `path` on `content` when the call or the config
+    /// asks for it. Formatter problems are reported, not raised: the content
+    /// is then written as given.
+    fn format_for_write(
+        &self,
+        path: &Path,
+        content: String,
+        requested: Option<bool>,
+    ) -> (String, Option<FormatReport>) {
+        // Formatting never runs a program from the project itself (see
+        // `format::select`), so calls may turn it on as well as off.
+        let settings = &self.config.format_on_write;
+        if !requested.unwrap_or(settings.enabled) {
+            return (content, None);
+        }
+        let top = if path.starts_with(&self.root) {
+            self.root.clone()
+        } else {
+            Self::find_git_root(path).unwrap_or_default()
+        };
+        let Some(formatter) = format::select(path, &top, settings) else {
+            let report = FormatReport {
+                formatter: None,
+                changed: false,
+                skipped: Some("no formatter is configured for this file".to_string()),
+            };
+            return (content, Some(report));
         };
 
-        match range_type {
-            RangeType::Bytes if has_line_params => {
-                return Err(FsError::ReadFileLinesWithBytes);
+        let (content, changed, skipped) = match formatter.run(&content, settings.timeout_ms) {
+            Ok(formatted) => {
+                let changed = formatted != content;
+                (formatted, changed, None)
             }
-            RangeType::Lines if has_byte_params => {
-                return Err(FsError::ReadFileBytesWithLines);
-            }
-            _ => {}
-        }
+            Err(message) => (content, false, Some(message)),
+        };
+        let report = FormatReport {
+            formatter: Some(formatter.name),
+            changed,
+            skipped,
+        };
+        (content, Some(report))
+    }
 
-        match range_type {
-            RangeType::Bytes => self.read_file_bytes(&abs_path, &args),
-            RangeType::Lines => self.read_file_lines(&abs_path, &args),
+    /// Read several files or ranges in one call. Each read is clamped to the
+    /// remaining byte / line budget, and a failure only affects its own item.
+    pub fn read_files(&self, args: ReadFilesArgs) -> Result<ReadFilesResult> {
+        let mut bytes_left = args.max_total_bytes.unwrap_or(DEFAULT_BATCH_MAX_BYTES);
+        let mut lines_left = args.max_total_lines;
+        let mut total_bytes = 0;
+        let mut budget_exhausted = false;
+        let mut files = Vec::with_capacity(args.files.len());
+
+        for mut item in args.files {
+            let path = item.path.clone();
+
+            if bytes_left == 0 || lines_left == Some(0) {
+                budget_exhausted = true;
+                files.push(ReadFilesItem {
+                    error: Some(FsError::ReadBudgetExhausted { path: path.clone() }.to_string()),
+                    path,
+                    chunk: None,
+                });
+                continue;
+            }
+
+            let read = Self::read_range_type(&item).and_then(|range_type| {
+                let lines_mode = range_type == RangeType::Lines;
+                if lines_mode {
+                    let max_lines = item.max_lines.unwrap_or(DEFAULT_MAX_READ_LINES);
+                    item.max_lines = Some(lines_left.map_or(max_lines, |left| max_lines.min(left)));
+                } else {
+                    // Encoded output is larger than the raw bytes it covers.
+                    let raw_left = match item.encoding {
+                        Some(ByteEncoding::Base64) => bytes_left / 4 * 3,
+                        Some(ByteEncoding::Hex) => bytes_left / 2,
+                        Some(ByteEncoding::Hexdump) => bytes_left / 79 * 16,
+                        None => bytes_left,
+                    };
+                    let max_bytes = item.max_bytes.unwrap_or(DEFAULT_MAX_READ_BYTES);
+                    item.max_bytes = Some(max_bytes.min(raw_left));
+                }
+                self.read_file(item).map(|chunk| (chunk, lines_mode))
+            });
+
+            let (mut chunk, lines_mode) = match read {
+                Ok(ok) => ok,
+                Err(err) => {
+                    files.push(ReadFilesItem {
+                        path,
+                        chunk: None,
+                        error: Some(err.to_string()),
+                    });
+                    continue;
+                }
+            };
+
+            // Lines-mode reads are only bounded by lines (and transcoded text
+            // can grow), so cut them back to whole lines that fit in the byte
+            // budget, or to a character boundary if no line fits.
+            if chunk.content.len() as u64 > bytes_left {
+                let mut end = bytes_left as usize;
+                while !chunk.content.is_char_boundary(end) {
+                    end -= 1;
+                }
+                let fit = chunk.content[..end].rfind('\n').map_or(end, |i| i + 1);
+                chunk.content.truncate(fit);
+                chunk.is_truncated = true;
+                chunk.tokens = chunk.tokens.map(|_| tokens::estimate(&chunk.content));
+                budget_exhausted = true;
+
+                // Keep the continuation fields pointing just past what is
+                // actually returned.
+                if lines_mode {
+                    let whole_lines = chunk.content.matches('\n').count() as u64;
+                    if let Some(ranges) = chunk.elided_ranges.as_mut() {
+                        // Elided views have no continuation; report the last
+                        // file line still shown.
+                        let spans: Vec<(u64, u64)> =
+                            ranges.iter().map(|r| (r.start_line, r.end_line)).collect();
+                        let end = elide::view_end(&spans, whole_lines);
+                        ranges.retain(|r| end.is_some_and(|last| r.end_line <= last));
+                        chunk.range.end_line = end;
+                    } else {
+                        let start = chunk.range.start_line.unwrap_or(1);
+                        chunk.range.end_line = whole_lines.checked_sub(1).map(|n| start + n);
+                        chunk.next_start_line = Some(start + whole_lines);
+                    }
+                } else {
+                    // Byte offsets only map onto content that was not
+                    // transcoded or re-encoded.
+                    chunk.next_offset_bytes = (chunk.encoding == TextEncoding::UTF8.name())
+                        .then(|| chunk.range.offset_bytes.unwrap_or(0) + fit as u64);
+                }
+            }
+
+            let used = chunk.content.len() as u64;
+            bytes_left -= used;
+            total_bytes += used;
+            if lines_mode && let Some(left) = lines_left.as_mut() {
+                *left = left.saturating_sub(chunk.content.matches('\n').count() as u64);
+            }
+            if chunk.is_truncated && (bytes_left == 0 || lines_left == Some(0)) {
+                budget_exhausted = true;
+            }
+
+            files.push(ReadFilesItem {
+                path,
+                chunk: Some(chunk),
+                error: None,
+            });
         }
+
+        Ok(ReadFilesResult {
+            files,
+            total_bytes,
+            budget_exhausted,
+        })
     }
 
-    fn read_file_bytes(&self, abs_path: &Path, args: &ReadFileArgs) -> Result<FileChunkResult> {
-        let offset = args.offset_bytes.unwrap_or(0);
-        let max_bytes = args.max_bytes.unwrap_or(DEFAULT_MAX_READ_BYTES);
+    /// Encode `content` for writing over the existing file at `path`, using
+    /// that file's text encoding (and BOM) so legacy-encoded files keep their
+    /// encoding. Binary or unreadable files get the content as-is.
+    fn encode_like_existing(path: &Path, content: &str) -> Result<Vec<u8>> {
+        let mut head = Vec::new();
+        if let Ok(file) = File::open(path) {
+            let _ = file
+                .take(content::ENCODING_SNIFF_LEN as u64)
+                .read_to_end(&mut head);
+        }
+        if content::is_binary(&head) {
+            return Ok(content.as_bytes().to_vec());
+        }
+
+        let encoding = content::detect_encoding(&head);
+        encoding
+            .encode(content)
+            .ok_or_else(|| FsError::UnencodableContent {
+                path: path.to_path_buf(),
+                encoding: encoding.name().to_string(),
+            })
+    }
+
+    /// Read an image or PDF for `read_file` as media content. Returns `None`
+    /// when the file is not media, or when any range or `encoding` argument
+    /// asks for a text / bytes read instead.
+    pub fn read_media(&self, args: &ReadFileArgs) -> Result<Option<MediaContent>> {
+        let explicit_range = args.range_type.is_some()
+            || args.offset_bytes.is_some()
+            || args.max_bytes.is_some()
+            || args.start_line.is_some()
+            || args.end_line.is_some()
+            || args.max_lines.is_some()
+            || args.line_numbers.is_some()
+            || args.max_tokens.is_some()
+            || args.elide.is_some()
+            || args.symbol.is_some()
+            || args.encoding.is_some();
+        if explicit_range || self.split_archive_member(&args.path).is_some() {
+            return Ok(None);
+        }
+
+        let abs_path = self.resolve_path(&args.path)?;
+        let meta = std::fs::metadata(&abs_path).map_err(|source| FsError::FileMetadata {
+            path: abs_path.clone(),
+            source,
+        })?;
+        if !meta.is_file() {
+            return Ok(None);
+        }
+
+        let mut head = [0u8; 16];
+        let head_len = File::open(&abs_path)
+            .and_then(|mut f| f.read(&mut head))
+            .map_err(|source| FsError::ReadFile {
+                path: abs_path.clone(),
+                source,
+            })?;
+        let Some(mime_type) = content::sniff_media_mime(&head[..head_len]) else {
+            return Ok(None);
+        };
+
+        let size = meta.len();
+        let source_limit = if mime_type == content::PDF_MIME {
+            MAX_PDF_BYTES
+        } else if args.max_image_dimension.is_some() {
+            MAX_IMAGE_SOURCE_BYTES
+        } else {
+            MAX_IMAGE_BYTES
+        };
+        if size > source_limit {
+            return Err(FsError::MediaTooLarge {
+                path: abs_path,
+                size,
+                limit: source_limit,
+            });
+        }
+
+        let mut data = std::fs::read(&abs_path).map_err(|source| FsError::ReadFile {
+            path: abs_path.clone(),
+            source,
+        })?;
+
+        let mut info = MediaInfo {
+            path: self
+                .strip_root(&abs_path)
+                .unwrap_or_else(|| abs_path.display().to_string()),
+            mime_type: mime_type.to_string(),
+            size,
+            width: None,
+            height: None,
+            downscaled: false,
+        };
+
+        if mime_type != content::PDF_MIME {
+            let decode_err = |source| FsError::DecodeImage {
+                path: abs_path.clone(),
+                source,
+            };
+            let format = image::guess_format(&data).map_err(decode_err)?;
+            let (width, height) = image::ImageReader::with_format(Cursor::new(&data), format)
+                .into_dimensions()
+                .map_err(decode_err)?;
+            info.width = Some(width);
+            info.height = Some(height);
+
+            if let Some(max_dim) = args.max_image_dimension
+                && width.max(height) > max_dim
+            {
+                let thumb = image::load_from_memory_with_format(&data, format)
+                    .map_err(decode_err)?
+                    .thumbnail(max_dim, max_dim);
+
+                // JPEG stays JPEG to keep photos small; everything else
+                // becomes PNG, which every client can display.
+                let (out_format, out_mime) = match format {
+                    image::ImageFormat::Jpeg => (image::ImageFormat::Jpeg, "image/jpeg"),
+                    _ => (image::ImageFormat::Png, "image/png"),
+                };
+                let mut out = Cursor::new(Vec::new());
+                let thumb = if out_format == image::ImageFormat::Jpeg {
+                    image::DynamicImage::ImageRgb8(thumb.to_rgb8())
+                } else {
+                    thumb
+                };
+                thumb.write_to(&mut out, out_format).map_err(decode_err)?;
+
+                data = out.into_inner();
+                info.mime_type = out_mime.to_string();
+                info.size = data.len() as u64;
+                info.width = Some(thumb.width());
+                info.height = Some(thumb.height());
+                info.downscaled = true;
+            }
+
+            if info.size > MAX_IMAGE_BYTES {
+                return Err(FsError::MediaTooLarge {
+                    path: abs_path,
+                    size: info.size,
+                    limit: MAX_IMAGE_BYTES,
+                });
+            }
+        }
+
+        Ok(Some(MediaContent {
+            info,
+            uri: format!("file://{}", abs_path.display()),
+            data,
+        }))
+    }
+
+    pub fn read_file(&self, args: ReadFileArgs) -> Result<FileChunkResult> {
+        if args.symbol.is_some() {
+            return self.read_symbol(args);
+        }
+        if let Some((archive_rel, member)) = self.split_archive_member(&args.path) {
+            return self.read_archive_member(archive_rel, member, &args);
+        }
 
-        let mut file = File::open(abs_path).map_err(|source| FsError::OpenFile {
+        let abs_path = self.resolve_path(&args.path)?;
+        let meta = std::fs::metadata(&abs_path).map_err(|source| FsError::FileMetadata {
+            path: abs_path.to_path_buf(),
+            source,
+        })?;
+        if !meta.is_file() {
+            return Err(FsError::ReadFileNotFile {
+                path: abs_path.to_path_buf(),
+            });
+        }
+        let range_type = Self::read_range_type(&args)?;
+
+        let mut file = File::open(&abs_path).map_err(|source| FsError::OpenFile {
             path: abs_path.to_path_buf(),
             source,
         })?;
+        let encoding = Self::sniff_encoding(&mut file, &abs_path)?;
+
+        match range_type {
+            RangeType::Bytes => self.read_file_bytes(file, meta.len(), &abs_path, encoding, &args),
+            RangeType::Lines => self.read_file_lines(file, meta.len(), &abs_path, encoding, &args),
+        }
+    }
+
+    /// `read_file` with `symbol`: resolve the symbol's line range from the
+    /// file outline and read exactly those lines.
+    fn read_symbol(&self, mut args: ReadFileArgs) -> Result<FileChunkResult> {
+        let symbol = args.symbol.take().unwrap_or_default();
+        if args.start_line.is_some() || args.end_line.is_some() {
+            return Err(FsError::ReadFileSymbolWithRange);
+        }
+
+        let (abs_path, language, source) = self.read_source(&args.path)?;
+        let symbols = outline::outline(language, &source);
+        let found = outline::find(&symbols, &symbol).ok_or_else(|| FsError::SymbolNotFound {
+            path: abs_path.clone(),
+            symbol: symbol.clone(),
+        })?;
+
+        args.start_line = Some(found.start_line as i64);
+        args.end_line = Some(found.end_line as i64);
+        // Return the whole item unless the caller set a limit.
+        if args.max_lines.is_none() && args.max_tokens.is_none() && args.elide != Some(true) {
+            args.max_lines = Some(found.end_line - found.start_line + 1);
+        }
+        self.read_file(args)
+    }
+
+    /// List the symbols declared in a source file.
+    pub fn file_outline(&self, args: FileOutlineArgs) -> Result<FileOutlineResult> {
+        let (abs_path, language, source) = self.read_source(&args.path)?;
+        let mut symbols = outline::outline(language, &source);
+        if let Some(max_depth) = args.max_depth {
+            outline::truncate_depth(&mut symbols, max_depth);
+        }
+
+        Ok(FileOutlineResult {
+            path: self
+                .strip_root(&abs_path)
+                .unwrap_or_else(|| abs_path.display().to_string()),
+            language: language.name().to_string(),
+            symbols,
+        })
+    }
+
+    /// Read a source file for parsing: detect its language from the
+    /// extension and decode it to UTF-8.
+    fn read_source(&self, path: &str) -> Result<(PathBuf, Language, String)> {
+        let abs_path = self.resolve_path(path)?;
+        let meta = std::fs::metadata(&abs_path).map_err(|source| FsError::FileMetadata {
+            path: abs_path.clone(),
+            source,
+        })?;
+        if !meta.is_file() {
+            return Err(FsError::ReadFileNotFile { path: abs_path });
+        }
+        let Some(language) = Language::for_path(&abs_path) else {
+            return Err(FsError::UnsupportedLanguage { path: abs_path });
+        };
+        if meta.len() > syntax::MAX_PARSE_BYTES {
+            return Err(FsError::SourceTooLarge {
+                path: abs_path,
+                size: meta.len(),
+                limit: syntax::MAX_PARSE_BYTES,
+            });
+        }
+
+        let bytes = std::fs::read(&abs_path).map_err(|source| FsError::ReadFile {
+            path: abs_path.clone(),
+            source,
+        })?;
+        let Some(source) = content::decode_text(&bytes) else {
+            return Err(FsError::FileNotUtf8 { path: abs_path });
+        };
+
+        Ok((abs_path, language, source))
+    }
+
+    /// Split `<archive>!/<member>` where the first half is an existing archive
+    /// file. Paths that only happen to contain `!/` are read as they are.
+    fn split_archive_member<'a>(&self, path: &'a str) -> Option<(&'a str, &'a str)> {
+        archive::member_path_splits(path).find(|(archive_rel, _)| {
+            self.resolve_path(archive_rel)
+                .is_ok_and(|path| path.is_file() && archive::is_archive(&path))
+        })
+    }
+
+    /// Detect the text encoding from the start of `reader`, then rewind it.
+    /// Binary content is treated as UTF-8 so it keeps failing the UTF-8
+    /// check instead of being decoded as some legacy encoding.
+    fn sniff_encoding(reader: &mut (impl Read + Seek), path: &Path) -> Result<TextEncoding> {
+        let mut head = Vec::with_capacity(content::ENCODING_SNIFF_LEN);
+        reader
+            .by_ref()
+            .take(content::ENCODING_SNIFF_LEN as u64)
+            .read_to_end(&mut head)
+            .and_then(|_| reader.seek(SeekFrom::Start(0)))
+            .map_err(|source| FsError::ReadFile {
+                path: path.to_path_buf(),
+                source,
+            })?;
+
+        if content::is_binary(&head) {
+            Ok(TextEncoding::UTF8)
+        } else {
+            Ok(content::detect_encoding(&head))
+        }
+    }
+
+    /// `read_file` for `<archive>!/<member>` paths: the member is decompressed
+    /// into memory and then read like a regular file.
+    fn read_archive_member(
+        &self,
+        archive_rel: &str,
+        member: &str,
+        args: &ReadFileArgs,
+    ) -> Result<FileChunkResult> {
+        let archive_path = self.resolve_path(archive_rel)?;
+        if !archive_path.is_file() {
+            return Err(FsError::ReadFileNotFile { path: archive_path });
+        }
+        let range_type = Self::read_range_type(args)?;
+
+        let bytes = archive::read_member(&archive_path, member).map_err(|source| {
+            if source.kind() == io::ErrorKind::NotFound {
+                FsError::ArchiveMemberNotFound {
+                    archive: archive_path.clone(),
+                    member: member.to_string(),
+                }
+            } else {
+                FsError::ReadArchive {
+                    path: archive_path.clone(),
+                    source,
+                }
+            }
+        })?;
+
+        let member_path = PathBuf::from(format!(
+            "{}{}{member}",
+            archive_path.display(),
+            archive::MEMBER_SEPARATOR
+        ));
+        let len = bytes.len() as u64;
+        let mut reader = Cursor::new(bytes);
+        let encoding = Self::sniff_encoding(&mut reader, &member_path)?;
+
+        match range_type {
+            RangeType::Bytes => self.read_file_bytes(reader, len, &member_path, encoding, args),
+            RangeType::Lines => self.read_file_lines(reader, len, &member_path, encoding, args),
+        }
+    }
+
+    fn read_range_type(args: &ReadFileArgs) -> Result<RangeType> {
+        let has_byte_params = args.offset_bytes.is_some() || args.max_bytes.is_some();
+        let has_line_params = args.start_line.is_some()
+            || args.end_line.is_some()
+            || args.max_lines.is_some()
+            || args.line_numbers.is_some()
+            || args.elide.is_some()
+            || args.symbol.is_some();
+
+        let range_type = match args.range_type {
+            Some(rt) => rt,
+            None if has_line_params => RangeType::Lines,
+            None => RangeType::Bytes,
+        };
+
+        match range_type {
+            RangeType::Bytes if has_line_params => {
+                return Err(FsError::ReadFileLinesWithBytes);
+            }
+            RangeType::Lines if has_byte_params => {
+                return Err(FsError::ReadFileBytesWithLines);
+            }
+            RangeType::Lines if args.encoding.is_some() => {
+                return Err(FsError::ReadFileEncodingWithLines);
+            }
+            _ => {}
+        }
+
+        Ok(range_type)
+    }
+
+    fn read_file_bytes(
+        &self,
+        mut file: impl Read + Seek,
+        file_len: u64,
+        abs_path: &Path,
+        encoding: TextEncoding,
+        args: &ReadFileArgs,
+    ) -> Result<FileChunkResult> {
+        let offset = args.offset_bytes.unwrap_or(0);
+        let max_bytes = args.max_bytes.unwrap_or(match args.max_tokens {
+            Some(max_tokens) => max_tokens.saturating_mul(BYTES_PER_TOKEN_WINDOW),
+            None => DEFAULT_MAX_READ_BYTES,
+        });
 
+        // Text reads also look at the bytes before the window to find
+        // character boundaries.
+        let lead_in = match args.encoding {
+            Some(_) => 0,
+            None => offset.min(content::SNAP_LEAD_IN as u64),
+        };
         if offset > 0 {
-            file.seek(SeekFrom::Start(offset))
+            file.seek(SeekFrom::Start(offset - lead_in))
                 .map_err(|source| FsError::SeekFile {
                     path: abs_path.to_path_buf(),
                     source,
@@ -1172,24 +2429,78 @@ impl LocalGitAwareFs {
         }
 
         let mut buf = Vec::new();
-        let mut limited = file.take(max_bytes);
+        let mut limited = file.take(
+            max_bytes
+                .saturating_add(lead_in)
+                .saturating_add(content::SNAP_SLACK as u64),
+        );
         limited
             .read_to_end(&mut buf)
             .map_err(|source| FsError::ReadFile {
                 path: abs_path.to_path_buf(),
                 source,
             })?;
+        let before = buf
+            .drain(..(lead_in as usize).min(buf.len()))
+            .collect::<Vec<_>>();
+
+        // Text reads snap to character boundaries so a multi-byte character
+        // is never split between two chunks; raw encodings return the exact
+        // byte window.
+        let window = usize::try_from(max_bytes).unwrap_or(usize::MAX);
+        let (start, end) = match args.encoding {
+            Some(_) => (0, window.min(buf.len())),
+            None => content::snap_to_chars(&buf, &before, offset, window, encoding),
+        };
+        let bytes = &buf[start..end];
+        let chunk_offset = offset + start as u64;
+
+        let mut lossy = false;
+        let mut content = match args.encoding {
+            Some(ByteEncoding::Base64) => BASE64_STANDARD.encode(bytes),
+            Some(ByteEncoding::Hex) => content::to_hex(bytes),
+            Some(ByteEncoding::Hexdump) => content::to_hexdump(bytes, chunk_offset),
+            None if encoding.is_utf8() => {
+                let (text, replaced) = content::decode_utf8_lossy(bytes.to_vec());
+                lossy = replaced;
+                text
+            }
+            None => encoding.decode(bytes).into_owned(),
+        };
 
-        let content = String::from_utf8(buf.clone()).map_err(|_| FsError::FileNotUtf8 {
-            path: abs_path.to_path_buf(),
-        })?;
+        let mut next_offset = offset + end as u64;
+        let mut tokens = None;
 
-        let metadata = std::fs::metadata(abs_path).map_err(|source| FsError::FileMetadata {
-            path: abs_path.to_path_buf(),
-            source,
-        })?;
-        let file_len = metadata.len();
-        let is_truncated = offset + max_bytes < file_len;
+        if let Some(max_tokens) = args.max_tokens
+            && args.encoding.is_none()
+        {
+            let fit = tokens::fit_prefix(&content, max_tokens);
+            // Map the kept text back to the number of source bytes it came
+            // from. Transcoded text is re-encoded; if it does not round-trip
+            // (malformed input decoded to U+FFFD) the chunk is kept whole.
+            let consumed = if lossy {
+                None
+            } else if encoding.is_utf8() {
+                Some(fit)
+            } else {
+                let bom = Encoding::for_bom(bytes)
+                    .filter(|(enc, _)| *enc == encoding.encoding)
+                    .map_or(0, |(_, len)| len);
+                TextEncoding {
+                    bom: false,
+                    ..encoding
+                }
+                .encode(&content[..fit])
+                .map(|b| bom + b.len())
+            };
+            if let Some(consumed) = consumed.filter(|_| fit < content.len()) {
+                content.truncate(fit);
+                next_offset = chunk_offset + consumed as u64;
+            }
+            tokens = Some(tokens::estimate(&content));
+        }
+
+        let is_truncated = next_offset < file_len;
 
         Ok(FileChunkResult {
             path: self
@@ -1199,54 +2510,219 @@ impl LocalGitAwareFs {
             is_truncated,
             range: FileRangeInfo {
                 range_type: RangeType::Bytes,
-                offset_bytes: Some(offset),
+                offset_bytes: Some(chunk_offset),
                 max_bytes: Some(max_bytes),
                 start_line: None,
+                end_line: None,
                 max_lines: None,
             },
+            next_offset_bytes: is_truncated.then_some(next_offset),
+            next_start_line: None,
+            file_size: file_len,
+            tokens,
+            elided_ranges: None,
+            total_lines: None,
+            encoding: if lossy {
+                content::UTF8_LOSSY_NAME
+            } else {
+                encoding.name()
+            }
+            .to_string(),
+            byte_encoding: args.encoding,
         })
     }
 
-    fn read_file_lines(&self, abs_path: &Path, args: &ReadFileArgs) -> Result<FileChunkResult> {
+    fn read_file_lines(
+        &self,
+        file: impl Read + 'static,
+        file_len: u64,
+        abs_path: &Path,
+        encoding: TextEncoding,
+        args: &ReadFileArgs,
+    ) -> Result<FileChunkResult> {
         let start_line = args.start_line.unwrap_or(1);
-        let max_lines = args.max_lines.unwrap_or(DEFAULT_MAX_READ_LINES);
+        // A token budget replaces the default line cap.
+        let max_lines = args.max_lines.unwrap_or(match args.max_tokens {
+            Some(_) => u64::MAX,
+            None => DEFAULT_MAX_READ_LINES,
+        });
+        let line_numbers = args.line_numbers.unwrap_or(false);
+        let elide = args.elide.unwrap_or(false);
 
-        if start_line == 0 {
-            return Err(FsError::StartLineMustBePositive);
+        if start_line == 0 || args.end_line == Some(0) {
+            return Err(FsError::LineNumberZero);
         }
 
-        let file = File::open(abs_path).map_err(|source| FsError::OpenFile {
-            path: abs_path.to_path_buf(),
-            source,
-        })?;
-        let reader = BufReader::new(file);
-
-        let mut content = String::new();
-        let mut current_line: u64 = 0;
-        let mut collected: u64 = 0;
-        let mut is_truncated = false;
+        let reader: Box<dyn Read> = if encoding.is_utf8() {
+            Box::new(file)
+        } else {
+            Box::new(
+                DecodeReaderBytesBuilder::new()
+                    .encoding(Some(encoding.encoding))
+                    .strip_bom(true)
+                    .build(file),
+            )
+        };
+        let mut reader = BufReader::new(reader);
 
-        for line_res in reader.lines() {
-            let line = line_res.map_err(|source| FsError::ReadLine {
-                path: abs_path.to_path_buf(),
-                source,
-            })?;
-            current_line += 1;
+        // A positive end bound is known up front; a negative one only once
+        // the total line count is, so those ranges are trimmed afterwards.
+        let known_end = match args.end_line {
+            Some(end) if end > 0 => end as u64,
+            _ => u64::MAX,
+        };
+        let tail_len = (start_line < 0).then(|| start_line.unsigned_abs());
+
+        // Candidate lines as (line number, raw text). Only the lines that
+        // are returned get decoded; the rest of the file is just counted for
+        // `total_lines`. An elided view covers the whole file, so every line
+        // is kept.
+        let mut kept: VecDeque<(u64, Vec<u8>)> = VecDeque::new();
+        let mut kept_tokens: u64 = 0;
+        let mut total_lines: u64 = 0;
+        let mut buf = Vec::new();
 
-            if current_line < start_line {
+        loop {
+            buf.clear();
+            let read = reader
+                .read_until(b'\n', &mut buf)
+                .map_err(|source| FsError::ReadLine {
+                    path: abs_path.to_path_buf(),
+                    source,
+                })?;
+            if read == 0 {
+                break;
+            }
+            total_lines += 1;
+
+            let wanted = match tail_len {
+                _ if elide => true,
+                Some(_) => true,
+                None => {
+                    total_lines >= start_line as u64
+                        && total_lines <= known_end
+                        && (kept.len() as u64) < max_lines
+                        && args.max_tokens.is_none_or(|max| kept_tokens <= max)
+                }
+            };
+            if !wanted {
                 continue;
             }
 
-            content.push_str(&line);
-            content.push('\n');
-            collected += 1;
+            let body = buf.strip_suffix(b"\n").unwrap_or(&buf);
+            let body = body.strip_suffix(b"\r").unwrap_or(body);
+            if args.max_tokens.is_some() && tail_len.is_none() {
+                kept_tokens += tokens::estimate(&String::from_utf8_lossy(body)) + 1;
+            }
+            kept.push_back((total_lines, body.to_vec()));
 
-            if collected >= max_lines {
-                is_truncated = true;
-                break;
+            if let Some(tail_len) = tail_len
+                && !elide
+                && kept.len() as u64 > tail_len
+            {
+                kept.pop_front();
+            }
+        }
+
+        let first_line = match tail_len {
+            Some(tail_len) => total_lines.saturating_sub(tail_len) + 1,
+            None => start_line as u64,
+        };
+        let last_line = match args.end_line {
+            Some(end) if end < 0 => (total_lines as i64 + end + 1).max(0) as u64,
+            _ => known_end,
+        };
+        let max_view_lines = usize::try_from(max_lines).unwrap_or(usize::MAX);
+        let lossy = Cell::new(false);
+        let decode = |bytes: Vec<u8>| {
+            let (text, replaced) = content::decode_utf8_lossy(bytes);
+            lossy.set(lossy.get() || replaced);
+            text
+        };
+
+        // The lines to render, each with the last file line it covers (an
+        // elision marker covers its whole range).
+        let (view, mut elided) = if elide {
+            let texts: Vec<String> = kept.into_iter().map(|(_, bytes)| decode(bytes)).collect();
+            let focus = if args.start_line.is_some() || args.end_line.is_some() {
+                (first_line, last_line.min(total_lines))
+            } else {
+                (1, 0)
+            };
+            let (view, elided) = elide::elide(&texts, focus);
+            let mut ranges = elided.iter();
+            let view: Vec<(ViewLine, u64)> = view
+                .into_iter()
+                .map(|line| {
+                    let covers = match line.number {
+                        Some(n) => n,
+                        None => ranges.next().map_or(0, |&(_, end)| end),
+                    };
+                    (line, covers)
+                })
+                .collect();
+            (view, elided)
+        } else {
+            let view: Vec<(ViewLine, u64)> = kept
+                .into_iter()
+                .filter(|(n, _)| *n >= first_line && *n <= last_line)
+                .take(max_view_lines)
+                .map(|(n, bytes)| {
+                    let line = ViewLine {
+                        number: Some(n),
+                        text: decode(bytes),
+                    };
+                    Ok((line, n))
+                })
+                .collect::<Result<_>>()?;
+            (view, Vec::new())
+        };
+        let view_start = if elide { 1 } else { first_line };
+
+        let number_width = if elide {
+            total_lines.to_string().len()
+        } else {
+            view[..view.len().min(max_view_lines)]
+                .last()
+                .map_or(0, |(_, n)| n.to_string().len())
+        };
+
+        let mut content = String::new();
+        let mut rendered = 0;
+        let mut used_tokens = 0;
+        let mut line = String::new();
+        for (view_line, _) in view.iter().take(max_view_lines) {
+            line.clear();
+            if line_numbers {
+                let number = view_line.number.map(|n| n.to_string()).unwrap_or_default();
+                let _ = write!(line, "{number:>number_width$}\t");
+            }
+            line.push_str(&view_line.text);
+            line.push('\n');
+
+            if let Some(max_tokens) = args.max_tokens {
+                let cost = tokens::estimate(&line);
+                // Always return at least one line so paging makes progress.
+                if rendered > 0 && used_tokens + cost > max_tokens {
+                    break;
+                }
+                used_tokens += cost;
             }
+            content.push_str(&line);
+            rendered += 1;
         }
 
+        let end_line = view[..rendered].last().map(|(_, covers)| *covers);
+        elided.retain(|&(_, end)| end_line.is_some_and(|last| end <= last));
+        // Truncated only when `max_lines` or `max_tokens` cut the requested
+        // range short, not merely because the file or `end_line` happens to
+        // end there.
+        let is_truncated = rendered < view.len()
+            || (!elide
+                && rendered as u64 >= max_lines
+                && end_line.is_some_and(|end| end < last_line.min(total_lines)));
+        let next_start_line = end_line.map_or(view_start, |end| end + 1).max(view_start);
+
         Ok(FileChunkResult {
             path: self
                 .strip_root(abs_path)
@@ -1257,24 +2733,45 @@ impl LocalGitAwareFs {
                 range_type: RangeType::Lines,
                 offset_bytes: None,
                 max_bytes: None,
-                start_line: Some(start_line),
-                max_lines: Some(max_lines),
+                start_line: Some(view_start),
+                end_line,
+                max_lines: (max_lines != u64::MAX).then_some(max_lines),
             },
+            next_offset_bytes: None,
+            // Re-reading with `elide` and a start line moves the focus
+            // instead of paging, so elided views have no continuation.
+            next_start_line: (next_start_line <= total_lines && !elide).then_some(next_start_line),
+            file_size: file_len,
+            tokens: args.max_tokens.map(|_| used_tokens),
+            elided_ranges: elide.then(|| {
+                elided
+                    .into_iter()
+                    .map(|(start_line, end_line)| LineRange {
+                        start_line,
+                        end_line,
+                    })
+                    .collect()
+            }),
+            total_lines: Some(total_lines),
+            encoding: if lossy.get() {
+                content::UTF8_LOSSY_NAME
+            } else {
+                encoding.name()
+            }
+            .to_string(),
+            byte_encoding: None,
         })
     }
 
-    pub fn search_text(&self, args: SearchTextArgs) -> Result<SearchTextResult> {
-        let mode = args.mode.unwrap_or(SearchMode::Literal);
-        let case_sensitive = args.case_sensitive.unwrap_or(false);
-        let max_results = args.max_results.unwrap_or(DEFAULT_MAX_SEARCH_RESULTS);
-        let context_lines = args.context_lines.unwrap_or(DEFAULT_SEARCH_CONTEXT_LINES);
-        let skip = args.skip.unwrap_or(0);
-
-        let root_arg = args.root.as_deref().unwrap_or(".");
+    /// Resolve the `root` argument shared by `search_text` and `replace_text`.
+    ///
+    /// Relative roots must stay inside the server root; absolute roots must be
+    /// inside some git repository.
+    fn resolve_search_root(&self, root: Option<&str>) -> Result<PathBuf> {
+        let root_arg = root.unwrap_or(".");
         let root_path = Path::new(root_arg);
-        let is_absolute = root_path.is_absolute();
 
-        let start_path = if is_absolute {
+        if root_path.is_absolute() {
             let canonical =
                 root_path
                     .canonicalize()
@@ -1287,7 +2784,7 @@ impl LocalGitAwareFs {
                 return Err(FsError::SearchRootNotInGit { path: canonical });
             }
 
-            canonical
+            Ok(canonical)
         } else {
             let joined = self.root.join(root_path);
             let canonical = joined
@@ -1301,53 +2798,248 @@ impl LocalGitAwareFs {
                 return Err(FsError::SearchRootEscapesRepo { path: canonical });
             }
 
-            canonical
-        };
+            Ok(canonical)
+        }
+    }
 
-        let include_globs = Self::build_globset(&args.include_globs)?.map(Arc::new);
-        let exclude_globs = Self::build_globset(&args.exclude_globs)?.map(Arc::new);
+    /// Per-language file, line and byte counts plus the largest files and
+    /// deepest directories under a root, from one walk.
+    pub fn repo_stats(
+        &self,
+        args: RepoStatsArgs,
+        control: &WalkControl,
+    ) -> Result<RepoStatsResult> {
+        let top = args.top.unwrap_or(DEFAULT_STATS_TOP) as usize;
+        let start_path = self.resolve_search_root(args.root.as_deref())?;
+
+        let include_globs = Self::build_globset(&args.include_globs)?;
+        let exclude_globs = Self::build_globset(&args.exclude_globs)?;
+        let walk = WalkOptions::new(
+            args.include_hidden,
+            args.no_ignore,
+            None,
+            args.follow_symlinks,
+            args.max_depth,
+        )
+        .with_types(self.build_types(&args.types, &args.exclude_types)?);
+        let builder = self.walk_builder(&start_path, walk);
+
+        let mut languages: HashMap<&'static str, LanguageStats> = HashMap::new();
+        // Min-heaps holding the `top` biggest entries seen so far.
+        let mut largest: BinaryHeap<Reverse<(u64, String, &'static str)>> = BinaryHeap::new();
+        let mut deepest: BinaryHeap<Reverse<(u64, String)>> = BinaryHeap::new();
+        let mut files: u64 = 0;
 
-        // Build a bytes-based regex matcher. Literal mode is implemented
-        // by escaping the query string.
-        let pattern_str = match mode {
-            SearchMode::Literal => regex::escape(&args.query),
-            SearchMode::Regex => args.query.clone(),
+        for result in builder.build() {
+            if control.should_stop() {
+                break;
+            }
+            control.tick(files);
+
+            let entry = match result {
+                Ok(e) => e,
+                Err(err) => {
+                    eprintln!("repo_stats: skip entry error: {err}");
+                    continue;
+                }
+            };
+            let path = entry.path();
+            let rel = path.strip_prefix(&start_path).unwrap_or(path);
+            if rel.as_os_str().is_empty() {
+                continue;
+            }
+            let rel_str = rel.to_string_lossy();
+            if exclude_globs
+                .as_ref()
+                .is_some_and(|g| g.is_match(rel_str.as_ref()))
+            {
+                continue;
+            }
+
+            let Some(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                deepest.push(Reverse((
+                    rel.components().count() as u64,
+                    rel_str.into_owned(),
+                )));
+                if deepest.len() > top {
+                    deepest.pop();
+                }
+                continue;
+            }
+            if !file_type.is_file()
+                || include_globs
+                    .as_ref()
+                    .is_some_and(|g| !g.is_match(rel_str.as_ref()))
+            {
+                continue;
+            }
+
+            let counted = File::open(path).and_then(|file| {
+                let bytes = file.metadata()?.len();
+                let mut reader = BufReader::new(file);
+                let binary = content::is_binary(reader.fill_buf()?);
+                match stats::language_for(path) {
+                    Some(language) if !binary => {
+                        Ok((language.name, bytes, stats::count_lines(language, reader)?))
+                    }
+                    _ => Ok(("Other", bytes, stats::LineCounts::default())),
+                }
+            });
+            let (language, bytes, lines) = match counted {
+                Ok(counted) => counted,
+                Err(err) => {
+                    eprintln!("repo_stats: skip file {}: {err}", path.display());
+                    continue;
+                }
+            };
+            files += 1;
+
+            let entry = languages.entry(language).or_insert_with(|| LanguageStats {
+                language: language.to_string(),
+                ..LanguageStats::default()
+            });
+            entry.files += 1;
+            entry.code += lines.code;
+            entry.comment += lines.comment;
+            entry.blank += lines.blank;
+            entry.bytes += bytes;
+
+            largest.push(Reverse((bytes, rel_str.into_owned(), language)));
+            if largest.len() > top {
+                largest.pop();
+            }
+        }
+
+        let mut languages: Vec<LanguageStats> = languages.into_values().collect();
+        languages
+            .sort_by(|a, b| (b.code, b.files, &a.language).cmp(&(a.code, a.files, &b.language)));
+        let mut total = LanguageStats {
+            language: "Total".to_string(),
+            ..LanguageStats::default()
+        };
+        for stats in &languages {
+            total.files += stats.files;
+            total.code += stats.code;
+            total.comment += stats.comment;
+            total.blank += stats.blank;
+            total.bytes += stats.bytes;
+        }
+
+        // `into_sorted_vec` on `Reverse` items yields the biggest first.
+        let largest_files = largest
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((bytes, path, language))| FileSize {
+                path,
+                bytes,
+                language: language.to_string(),
+            })
+            .collect();
+        let deepest_directories = deepest
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((depth, path))| DirectoryDepth { path, depth })
+            .collect();
+
+        Ok(RepoStatsResult {
+            languages,
+            total,
+            largest_files,
+            deepest_directories,
+            timed_out: control.timed_out(),
+        })
+    }
+
+    /// Build the bytes-based regex matcher used by `search_text` and
+    /// `replace_text`. Literal mode is implemented by escaping the query.
+    fn build_search_matcher(
+        query: &str,
+        mode: SearchMode,
+        case_sensitive: bool,
+    ) -> Result<ByteRegex> {
+        let pattern_str = match mode {
+            SearchMode::Literal => regex::escape(query),
+            SearchMode::Regex => query.to_string(),
         };
 
-        let matcher = ByteRegexBuilder::new(&pattern_str)
+        ByteRegexBuilder::new(&pattern_str)
             .case_insensitive(!case_sensitive)
             .build()
             .map_err(|source| FsError::InvalidSearchRegex {
-                query: args.query.clone(),
+                query: query.to_string(),
                 source,
-            })?;
+            })
+    }
 
-        let matcher = Arc::new(matcher);
-        let hits: Arc<Mutex<Vec<SearchHit>>> = Arc::new(Mutex::new(Vec::new()));
+    pub fn search_text(
+        &self,
+        args: SearchTextArgs,
+        control: &WalkControl,
+    ) -> Result<SearchTextResult> {
+        let mode = args.mode.unwrap_or(SearchMode::Literal);
+        let case_sensitive = args.case_sensitive.unwrap_or(false);
+        let max_results = args.max_results.unwrap_or(DEFAULT_MAX_SEARCH_RESULTS);
+        let context_lines = args.context_lines.unwrap_or(DEFAULT_SEARCH_CONTEXT_LINES);
+        let skip = args.skip.unwrap_or(0);
+        let search_binary = args.search_binary.unwrap_or(false);
+        let rank = args.rank.unwrap_or(false);
+        let search_archives = args.search_archives.unwrap_or(false);
+
+        // Ranking needs the whole candidate pool before paging, so collect up
+        // to a fixed cap first and apply `skip` / `max_results` afterwards.
+        let (collect_skip, collect_max) = if rank {
+            (0, MAX_RANK_CANDIDATES)
+        } else {
+            (skip, max_results)
+        };
 
-        // Global counters across all threads: how many matches have been
-        // seen (for `skip`) and whether we hit the `max_results` cap.
-        let seen_matches = Arc::new(AtomicU32::new(0));
-        let hit_limit = Arc::new(AtomicBool::new(false));
+        let start_path = self.resolve_search_root(args.root.as_deref())?;
+
+        let include_globs = Self::build_globset(&args.include_globs)?.map(Arc::new);
+        let exclude_globs = Self::build_globset(&args.exclude_globs)?.map(Arc::new);
+        let walk = WalkOptions::new(
+            args.include_hidden,
+            args.no_ignore,
+            args.no_ignore_vcs,
+            args.follow_symlinks,
+            args.max_depth,
+        )
+        .with_types(self.build_types(&args.types, &args.exclude_types)?);
+
+        let matcher = Self::build_search_matcher(&args.query, mode, case_sensitive)?;
+        let state = Arc::new(SearchState {
+            matcher,
+            context_lines: context_lines as usize,
+            skip: collect_skip,
+            max_results: collect_max,
+            search_binary,
+            hits: Mutex::new(Vec::new()),
+            line_counts: Mutex::new(HashMap::new()),
+            seen_matches: AtomicU32::new(0),
+            hit_limit: AtomicBool::new(false),
+            control: control.clone(),
+        });
 
         let repo_root = self.root.clone();
-        let mut builder = WalkBuilder::new(&start_path);
-        builder.standard_filters(true);
+        let builder = self.walk_builder(&start_path, walk);
 
         builder.build_parallel().run(|| {
-            let matcher = matcher.clone();
-            let hits = hits.clone();
-            let seen_matches = seen_matches.clone();
-            let hit_limit = hit_limit.clone();
+            let state = state.clone();
             let include_globs = include_globs.clone();
             let exclude_globs = exclude_globs.clone();
             let start_path = start_path.clone();
             let repo_root = repo_root.clone();
 
             Box::new(move |entry_res| {
-                if hit_limit.load(Ordering::Relaxed) {
+                if state.should_stop() {
                     return ignore::WalkState::Quit;
                 }
+                state
+                    .control
+                    .tick(state.seen_matches.load(Ordering::Relaxed) as u64);
 
                 let entry = match entry_res {
                     Ok(e) => e,
@@ -1381,6 +3073,37 @@ impl LocalGitAwareFs {
                     return ignore::WalkState::Continue;
                 }
 
+                let display_path = match path.strip_prefix(&repo_root) {
+                    Ok(r) => r.to_string_lossy().into_owned(),
+                    Err(_) => path.display().to_string(),
+                };
+
+                if search_archives && archive::is_archive(path) {
+                    let mut keep_going = true;
+                    let res = archive::for_each_member(path, &mut |member, bytes| {
+                        if state.should_stop() {
+                            keep_going = false;
+                            return false;
+                        }
+                        if bytes.is_empty() {
+                            return true;
+                        }
+                        let member_path =
+                            format!("{display_path}{}{member}", archive::MEMBER_SEPARATOR);
+                        keep_going = state.search_content(bytes, &member_path);
+                        keep_going
+                    });
+                    if let Err(err) = res {
+                        eprintln!("search_text: skip archive {}: {err}", path.display());
+                    }
+
+                    return if keep_going {
+                        ignore::WalkState::Continue
+                    } else {
+                        ignore::WalkState::Quit
+                    };
+                }
+
                 let file = match File::open(path) {
                     Ok(f) => f,
                     Err(err) => {
@@ -1404,134 +3127,1336 @@ impl LocalGitAwareFs {
                     return ignore::WalkState::Continue;
                 }
 
-                // Precompute line start offsets (0-based byte indices).
-                let mut line_starts: Vec<usize> = Vec::new();
-                line_starts.push(0);
-                for (i, &b) in mmap.iter().enumerate() {
-                    if b == b'\n' && i + 1 < mmap.len() {
-                        line_starts.push(i + 1);
-                    }
+                if state.search_content(&mmap, &display_path) {
+                    ignore::WalkState::Continue
+                } else {
+                    ignore::WalkState::Quit
                 }
+            })
+        });
+
+        let mut hits = {
+            let mut guard = state
+                .hits
+                .lock()
+                .expect("search_text: hits mutex poisoned at final collection");
+            std::mem::take(&mut *guard)
+        };
+        let mut has_more = state.is_done();
+
+        if rank {
+            let line_counts = std::mem::take(
+                &mut *state
+                    .line_counts
+                    .lock()
+                    .expect("search_text: line counts mutex poisoned"),
+            );
+            rank::rank_hits(&mut hits, |path| {
+                self.rank_file_info(path, line_counts.get(path).copied())
+            });
+
+            let end = skip as usize + max_results as usize;
+            has_more = has_more || hits.len() > end;
+            hits = hits
+                .into_iter()
+                .skip(skip as usize)
+                .take(max_results as usize)
+                .collect();
+        }
+
+        Ok(SearchTextResult {
+            hits,
+            has_more,
+            timed_out: control.timed_out(),
+        })
+    }
+
+    /// Find symbol definitions by name across the repository, using the
+    /// cached per-file symbol index.
+    pub fn search_symbols(
+        &self,
+        args: SearchSymbolsArgs,
+        control: &WalkControl,
+    ) -> Result<SearchSymbolsResult> {
+        let mode = args.mode.unwrap_or(SymbolMatchMode::Substring);
+        let case_sensitive = args.case_sensitive.unwrap_or(false);
+        let max_results = args.max_results.unwrap_or(DEFAULT_MAX_SYMBOL_RESULTS) as usize;
+        let skip = args.skip.unwrap_or(0) as usize;
+        let kinds = args.kinds.unwrap_or_default();
+        let qualified = args.query.contains("::") || args.query.contains('.');
+
+        let start_path = self.resolve_search_root(args.root.as_deref())?;
+
+        let include_globs = Self::build_globset(&args.include_globs)?;
+        let exclude_globs = Self::build_globset(&args.exclude_globs)?;
+        let walk = WalkOptions::new(
+            args.include_hidden,
+            args.no_ignore,
+            args.no_ignore_vcs,
+            args.follow_symlinks,
+            args.max_depth,
+        )
+        .with_types(self.build_types(&args.types, &args.exclude_types)?);
+
+        let fuzzy = match mode {
+            SymbolMatchMode::Fuzzy if case_sensitive => {
+                Some(SkimMatcherV2::default().respect_case())
+            }
+            SymbolMatchMode::Fuzzy => Some(SkimMatcherV2::default().ignore_case()),
+            _ => None,
+        };
+        let query = if case_sensitive {
+            args.query.clone()
+        } else {
+            args.query.to_lowercase()
+        };
+        // Match `::` and `.` qualified queries alike.
+        let normalize = |name: &str| {
+            let name = name.replace("::", ".");
+            if case_sensitive {
+                name
+            } else {
+                name.to_lowercase()
+            }
+        };
+        let query = normalize(&query);
 
-                let line_count = line_starts.len();
+        let matches: Mutex<Vec<SymbolMatch>> = Mutex::new(Vec::new());
+        let seen = AtomicU32::new(0);
+        let repo_root = &self.root;
 
-                for (idx, &line_start) in line_starts.iter().enumerate() {
-                    if hit_limit.load(Ordering::Relaxed) {
+        self.walk_builder(&start_path, walk)
+            .build_parallel()
+            .run(|| {
+                Box::new(|entry_res| {
+                    if control.should_stop() {
                         return ignore::WalkState::Quit;
                     }
+                    control.tick(seen.load(Ordering::Relaxed) as u64);
 
-                    let line_end = if idx + 1 < line_count {
-                        line_starts[idx + 1].saturating_sub(1)
-                    } else {
-                        mmap.len()
+                    let entry = match entry_res {
+                        Ok(e) => e,
+                        Err(err) => {
+                            eprintln!("search_symbols: skip entry error: {err}");
+                            return ignore::WalkState::Continue;
+                        }
                     };
-
-                    if line_start >= line_end || line_end > mmap.len() {
-                        continue;
+                    let path = entry.path();
+                    if !entry.file_type().is_some_and(|t| t.is_file()) {
+                        return ignore::WalkState::Continue;
                     }
+                    let Some(language) = Language::for_path(path) else {
+                        return ignore::WalkState::Continue;
+                    };
 
-                    let line_slice = &mmap[line_start..line_end];
+                    let rel = path.strip_prefix(&start_path).unwrap_or(path);
+                    let rel_str = rel.to_string_lossy();
+                    if exclude_globs
+                        .as_ref()
+                        .is_some_and(|g| g.is_match(rel_str.as_ref()))
+                        || include_globs
+                            .as_ref()
+                            .is_some_and(|g| !g.is_match(rel_str.as_ref()))
+                    {
+                        return ignore::WalkState::Continue;
+                    }
 
-                    // Find the first match in this line (keep behavior
-                    // of at most one hit per line).
-                    let mat = match matcher.find(line_slice) {
-                        Some(m) => m,
-                        None => continue,
+                    let symbols = match self.symbol_index.symbols(path, language) {
+                        Ok(symbols) => symbols,
+                        Err(err) => {
+                            eprintln!("search_symbols: skip file {}: {err}", path.display());
+                            return ignore::WalkState::Continue;
+                        }
                     };
 
-                    let col_idx = mat.start();
-
-                    let seen_before = seen_matches.fetch_add(1, Ordering::Relaxed);
-                    let seen_after = seen_before + 1;
+                    let display_path = match path.strip_prefix(repo_root) {
+                        Ok(r) => r.to_string_lossy().into_owned(),
+                        Err(_) => path.display().to_string(),
+                    };
 
-                    if seen_after <= skip {
-                        continue;
+                    let mut found = Vec::new();
+                    for symbol in symbols.iter() {
+                        if !kinds.is_empty() && !kinds.contains(&symbol.kind) {
+                            continue;
+                        }
+                        let haystack = match (&symbol.container, qualified) {
+                            (Some(container), true) => normalize(&format!(
+                                "{container}{}{}",
+                                language.path_separator(),
+                                symbol.name
+                            )),
+                            _ => normalize(&symbol.name),
+                        };
+                        let score = match (&fuzzy, mode) {
+                            (Some(fuzzy), _) => match fuzzy.fuzzy_match(&haystack, &query) {
+                                Some(score) => Some(score),
+                                None => continue,
+                            },
+                            (None, SymbolMatchMode::Exact) if haystack == query => None,
+                            (None, SymbolMatchMode::Substring) if haystack.contains(&query) => None,
+                            _ => continue,
+                        };
+                        found.push(SymbolMatch {
+                            path: display_path.clone(),
+                            name: symbol.name.clone(),
+                            kind: symbol.kind,
+                            start_line: symbol.start_line,
+                            end_line: symbol.end_line,
+                            container: symbol.container.clone(),
+                            signature: symbol.signature.clone(),
+                            score,
+                        });
                     }
 
-                    // Check and push into the shared hits vector.
-                    let mut guard = hits.lock().expect("search_text: hits mutex poisoned");
-                    if guard.len() as u32 >= max_results {
-                        hit_limit.store(true, Ordering::Relaxed);
-                        return ignore::WalkState::Quit;
+                    if !found.is_empty() {
+                        seen.fetch_add(found.len() as u32, Ordering::Relaxed);
+                        matches
+                            .lock()
+                            .expect("search_symbols: matches mutex poisoned")
+                            .extend(found);
                     }
+                    ignore::WalkState::Continue
+                })
+            });
 
-                    let line_num = idx as u64 + 1;
-                    let col = col_idx as u64;
+        let mut symbols = matches
+            .into_inner()
+            .expect("search_symbols: matches mutex poisoned at final collection");
+
+        // Best score first for fuzzy matches; otherwise names equal to the
+        // query before longer ones. Ties are broken by location so results
+        // are deterministic despite the parallel walk.
+        symbols.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then_with(|| a.name.len().cmp(&b.name.len()))
+                .then_with(|| a.path.cmp(&b.path))
+                .then_with(|| a.start_line.cmp(&b.start_line))
+        });
 
-                    let start_ctx = idx.saturating_sub(context_lines as usize);
-                    let end_ctx = usize::min(line_count, idx + 1 + context_lines as usize);
+        let has_more = symbols.len() > skip.saturating_add(max_results);
+        let symbols = symbols.into_iter().skip(skip).take(max_results).collect();
 
-                    let mut context_before = Vec::new();
-                    let mut context_after = Vec::new();
+        Ok(SearchSymbolsResult {
+            symbols,
+            has_more,
+            timed_out: control.timed_out(),
+        })
+    }
 
-                    for ctx_idx in start_ctx..end_ctx {
-                        if ctx_idx == idx {
-                            continue;
-                        }
+    /// Match a code pattern with metavariables against the syntax trees of
+    /// source files, optionally rewriting each match from a template.
+    pub fn search_structural(
+        &self,
+        args: SearchStructuralArgs,
+        control: &WalkControl,
+    ) -> Result<SearchStructuralResult> {
+        let max_results = args.max_results.unwrap_or(DEFAULT_MAX_SEARCH_RESULTS) as usize;
+        let context_lines = args.context_lines.unwrap_or(DEFAULT_SEARCH_CONTEXT_LINES) as usize;
+        let action = args.action.unwrap_or(ReplaceAction::Preview);
+        let max_files = args.max_files.unwrap_or(DEFAULT_REPLACE_MAX_FILES);
+
+        let languages = match args.language.as_deref() {
+            Some(name) => {
+                vec![
+                    Language::from_name(name).ok_or_else(|| FsError::UnknownLanguage {
+                        name: name.to_string(),
+                    })?,
+                ]
+            }
+            None => Language::ALL.to_vec(),
+        };
+        // A pattern only needs to parse in the languages it is meant for;
+        // files of the other languages are skipped.
+        let patterns: HashMap<Language, Pattern> = languages
+            .into_iter()
+            .filter_map(|language| Some((language, Pattern::parse(language, &args.pattern)?)))
+            .collect();
+        if patterns.is_empty() {
+            return Err(FsError::InvalidPattern {
+                pattern: args.pattern,
+            });
+        }
 
-                        let ctx_start = line_starts[ctx_idx];
-                        let ctx_end = if ctx_idx + 1 < line_count {
-                            line_starts[ctx_idx + 1].saturating_sub(1)
-                        } else {
-                            mmap.len()
-                        };
+        let start_path = self.resolve_search_root(args.root.as_deref())?;
+
+        let include_globs = Self::build_globset(&args.include_globs)?;
+        let exclude_globs = Self::build_globset(&args.exclude_globs)?;
+        let walk = WalkOptions::new(
+            args.include_hidden,
+            args.no_ignore,
+            None,
+            args.follow_symlinks,
+            args.max_depth,
+        );
+
+        // Walk sequentially in path order, like `replace_text`, so hits and
+        // the files selected by `max_files` are deterministic.
+        let mut builder = self.walk_builder(&start_path, walk);
+        builder.sort_by_file_path(|a, b| a.cmp(b));
+
+        let mut hits = Vec::new();
+        let mut has_more = false;
+        let mut changes = Vec::new();
+        let mut more_files = false;
+        let mut stopped = false;
 
-                        if ctx_start >= ctx_end || ctx_end > mmap.len() {
-                            continue;
-                        }
+        for result in builder.build() {
+            if control.should_stop() {
+                stopped = true;
+                break;
+            }
+            control.tick(hits.len() as u64);
 
-                        let ctx_slice = &mmap[ctx_start..ctx_end];
-                        let ctx_text = String::from_utf8_lossy(ctx_slice).to_string();
+            let entry = match result {
+                Ok(e) => e,
+                Err(err) => {
+                    eprintln!("search_structural: skip entry error: {err}");
+                    continue;
+                }
+            };
+            let path = entry.path();
+            if !entry.file_type().is_some_and(|t| t.is_file()) {
+                continue;
+            }
+            let Some((language, pattern)) =
+                Language::for_path(path).and_then(|l| Some((l, patterns.get(&l)?)))
+            else {
+                continue;
+            };
 
-                        if ctx_idx < idx {
-                            context_before.push(ctx_text);
-                        } else {
-                            context_after.push(ctx_text);
-                        }
-                    }
+            let rel = path.strip_prefix(&start_path).unwrap_or(path);
+            let rel_str = rel.to_string_lossy();
+            if exclude_globs
+                .as_ref()
+                .is_some_and(|g| g.is_match(rel_str.as_ref()))
+                || include_globs
+                    .as_ref()
+                    .is_some_and(|g| !g.is_match(rel_str.as_ref()))
+            {
+                continue;
+            }
 
-                    let rel = match path.strip_prefix(&repo_root) {
-                        Ok(r) => r.to_string_lossy().into_owned(),
-                        Err(_) => path.display().to_string(),
-                    };
+            if entry
+                .metadata()
+                .is_ok_and(|meta| meta.len() > syntax::MAX_PARSE_BYTES)
+            {
+                continue;
+            }
+            let bytes = match std::fs::read(path) {
+                Ok(b) => b,
+                Err(err) => {
+                    eprintln!(
+                        "search_structural: skip unreadable file {}: {err}",
+                        path.display()
+                    );
+                    continue;
+                }
+            };
+            let Some((encoding, source)) =
+                Self::decode_for_rewrite("search_structural", path, bytes)
+            else {
+                continue;
+            };
+            let Some(tree) = language.parse(&source) else {
+                continue;
+            };
 
-                    let line_text = String::from_utf8_lossy(line_slice).to_string();
+            let matches = pattern.find_all(&tree, &source);
+            if matches.is_empty() {
+                continue;
+            }
 
-                    guard.push(SearchHit {
-                        path: rel,
-                        line: line_num,
-                        column: col,
-                        line_text,
-                        context_before,
-                        context_after,
+            let display_path = self
+                .strip_root(path)
+                .unwrap_or_else(|| path.display().to_string());
+
+            if hits.len() < max_results {
+                let lines: Vec<&str> = source.lines().collect();
+                for mat in &matches {
+                    let (row, column) = mat.start;
+                    let before = row.saturating_sub(context_lines);
+                    let after = (mat.end_row + 1 + context_lines).min(lines.len());
+                    hits.push(SearchHit {
+                        path: display_path.clone(),
+                        line: row as u64 + 1,
+                        column: column as u64,
+                        line_text: lines.get(row).copied().unwrap_or_default().to_string(),
+                        context_before: lines[before..row].iter().map(|l| l.to_string()).collect(),
+                        context_after: lines
+                            .get(mat.end_row + 1..after)
+                            .unwrap_or_default()
+                            .iter()
+                            .map(|l| l.to_string())
+                            .collect(),
+                        binary: false,
+                        encoding: (!encoding.is_utf8()).then(|| encoding.name().to_string()),
+                        score: None,
+                        end_line: Some(mat.end_row as u64 + 1),
+                        captures: Some(mat.captures.clone()),
                     });
+                }
+            } else {
+                has_more = true;
+            }
+            if hits.len() > max_results {
+                hits.truncate(max_results);
+                has_more = true;
+            }
 
-                    if guard.len() as u32 >= max_results {
-                        hit_limit.store(true, Ordering::Relaxed);
-                        return ignore::WalkState::Quit;
+            if let Some(template) = &args.rewrite {
+                if changes.len() as u32 >= max_files {
+                    more_files = true;
+                } else {
+                    let (replaced, count) = structural::rewrite(&source, &matches, template);
+
+                    if replaced != source {
+                        match encoding.encode(&replaced) {
+                            Some(encoded) => changes.push(PendingRewrite {
+                                path: path.to_path_buf(),
+                                original: source,
+                                replaced,
+                                encoded,
+                                count,
+                            }),
+                            None => eprintln!(
+                                "search_structural: skip file, rewrite not representable in {}: {}",
+                                encoding.name(),
+                                path.display()
+                            ),
+                        }
                     }
                 }
+            }
 
-                ignore::WalkState::Continue
+            if has_more && (args.rewrite.is_none() || more_files) {
+                break;
+            }
+        }
+
+        let rewrite = match args.rewrite {
+            Some(_) => {
+                // A walk cut short by a timeout or cancellation has not seen
+                // every file, so a partial rewrite is never applied.
+                let action = if stopped {
+                    ReplaceAction::Preview
+                } else {
+                    action
+                };
+                let (files, total_replacements) = self.finish_rewrites(changes, action)?;
+                Some(ReplaceTextResult {
+                    files,
+                    total_replacements,
+                    applied: action == ReplaceAction::Apply,
+                    has_more: more_files || stopped,
+                    timed_out: control.timed_out(),
+                })
+            }
+            None => None,
+        };
+
+        Ok(SearchStructuralResult {
+            hits,
+            has_more,
+            timed_out: control.timed_out(),
+            rewrite,
+        })
+    }
+
+    /// Jump to the definition of the symbol at a position, as reported by
+    /// the configured language server.
+    pub fn goto_definition(&self, args: LspPositionArgs) -> Result<LspLocationsResult> {
+        let (client, params, _) = self.lsp_position(&args.path, args.line, args.column)?;
+        let result = client.request("textDocument/definition", params)?;
+        Ok(LspLocationsResult {
+            locations: self.lsp_locations(&client, &result),
+            has_more: false,
+        })
+    }
+
+    /// List references to the symbol at a position.
+    pub fn find_references(&self, args: FindReferencesArgs) -> Result<LspLocationsResult> {
+        let max_results = args.max_results.unwrap_or(DEFAULT_MAX_REFERENCES) as usize;
+        let (client, mut params, _) = self.lsp_position(&args.path, args.line, args.column)?;
+        params["context"] = serde_json::json!({
+            "includeDeclaration": args.include_declaration.unwrap_or(true)
+        });
+        let result = client.request("textDocument/references", params)?;
+
+        let mut locations = self.lsp_locations(&client, &result);
+        locations.sort_by(|a, b| {
+            a.path
+                .cmp(&b.path)
+                .then_with(|| a.line.cmp(&b.line))
+                .then_with(|| a.column.cmp(&b.column))
+        });
+        let has_more = locations.len() > max_results;
+        locations.truncate(max_results);
+        Ok(LspLocationsResult {
+            locations,
+            has_more,
+        })
+    }
+
+    /// Type and documentation of the symbol at a position.
+    pub fn hover(&self, args: LspPositionArgs) -> Result<HoverResult> {
+        let (client, params, _) = self.lsp_position(&args.path, args.line, args.column)?;
+        let result = client.request("textDocument/hover", params)?;
+        Ok(HoverResult {
+            contents: lsp::hover_text(&result),
+        })
+    }
+
+    /// Diagnostics the language server publishes for a file, or the ones
+    /// found in compiler output when `output` is given.
+    pub fn diagnostics(&self, args: DiagnosticsArgs) -> Result<DiagnosticsResult> {
+        if let Some(output) = &args.output {
+            return self.parse_diagnostics(output, &args);
+        }
+        let Some(path) = args.path.as_deref() else {
+            return Err(FsError::DiagnosticsTargetMissing);
+        };
+        let wait = Duration::from_millis(args.wait_ms.unwrap_or(DEFAULT_DIAGNOSTICS_WAIT_MS));
+        let abs_path = self.resolve_path(path)?;
+        let client = self.lsp.client_for(&abs_path)?;
+
+        // Unchanged files that already have diagnostics need no waiting.
+        let since = client.diagnostics_generation(&abs_path);
+        let (text, sent) = self.lsp_sync(&client, &abs_path)?;
+        let wait = if sent || since == 0 {
+            wait
+        } else {
+            Duration::ZERO
+        };
+        let published = client.diagnostics(&abs_path, since, wait);
+
+        let lines: Vec<&str> = text.lines().collect();
+        let display_path = self
+            .strip_root(&abs_path)
+            .unwrap_or_else(|| abs_path.display().to_string());
+        let diagnostics = published
+            .iter()
+            .filter_map(|item| {
+                let range = lsp::range_of(item)?;
+                let column = |line: u32, character: u32| {
+                    let text = lines.get(line as usize).copied().unwrap_or_default();
+                    client.to_byte(text, character) as u64
+                };
+                let severity = match item.get("severity").and_then(|s| s.as_u64()) {
+                    Some(2) => DiagnosticSeverity::Warning,
+                    Some(3) => DiagnosticSeverity::Info,
+                    Some(4) => DiagnosticSeverity::Hint,
+                    _ => DiagnosticSeverity::Error,
+                };
+                let code = item.get("code").and_then(|code| match code {
+                    serde_json::Value::String(s) => Some(s.clone()),
+                    serde_json::Value::Number(n) => Some(n.to_string()),
+                    _ => None,
+                });
+                Some(Diagnostic {
+                    path: display_path.clone(),
+                    line: range.start.line as u64 + 1,
+                    column: column(range.start.line, range.start.character),
+                    end_line: Some(range.end.line as u64 + 1),
+                    end_column: Some(column(range.end.line, range.end.character)),
+                    severity,
+                    code,
+                    message: item.get("message")?.as_str()?.to_string(),
+                    source: item
+                        .get("source")
+                        .and_then(|s| s.as_str())
+                        .map(str::to_string),
+                })
             })
+            .collect();
+
+        Ok(DiagnosticsResult { diagnostics })
+    }
+
+    /// Diagnostics from compiler or linter output, with paths made relative
+    /// to the root and columns converted to byte offsets in the files as
+    /// they are now.
+    fn parse_diagnostics(&self, output: &str, args: &DiagnosticsArgs) -> Result<DiagnosticsResult> {
+        let format = args.format.unwrap_or(DiagnosticsFormat::Auto);
+        let reported = compiler_output::parse(output, format).map_err(|source| {
+            FsError::InvalidCompilerOutput {
+                format: format.name().to_string(),
+                source,
+            }
+        })?;
+        let cwd = match args.cwd.as_deref() {
+            Some(cwd) => self.resolve_path(cwd)?,
+            None => self.root.clone(),
+        };
+        let only = args
+            .path
+            .as_deref()
+            .map(|path| self.resolve_path(path))
+            .transpose()?;
+
+        let mut files: HashMap<PathBuf, Vec<String>> = HashMap::new();
+        let mut seen = HashSet::new();
+        let mut diagnostics = Vec::new();
+        for item in reported {
+            let abs_path = normalize_lexically(&cwd.join(&item.path));
+            if only.as_ref().is_some_and(|only| *only != abs_path) {
+                continue;
+            }
+            // cargo reports the same warning once per target that builds the file.
+            if !seen.insert((
+                abs_path.clone(),
+                item.line,
+                item.column,
+                item.message.clone(),
+            )) {
+                continue;
+            }
+
+            let lines = files.entry(abs_path.clone()).or_insert_with(|| {
+                std::fs::read(&abs_path)
+                    .ok()
+                    .and_then(|bytes| content::decode_text(&bytes))
+                    .map(|text| text.lines().map(str::to_string).collect())
+                    .unwrap_or_default()
+            });
+            let column = |line: u64, column: u64, unit: ColumnUnit| match lines
+                .get(line.saturating_sub(1) as usize)
+            {
+                Some(text) => compiler_output::byte_column(text, column, unit),
+                None => column.saturating_sub(1),
+            };
+            let end = item
+                .end
+                .map(|(line, end)| (line, column(line, end, item.unit)));
+            diagnostics.push(Diagnostic {
+                path: self
+                    .strip_root(&abs_path)
+                    .unwrap_or_else(|| abs_path.display().to_string()),
+                line: item.line,
+                column: column(item.line, item.column, item.unit),
+                end_line: end.map(|(line, _)| line),
+                end_column: end.map(|(_, column)| column),
+                severity: item.severity,
+                code: item.code,
+                message: item.message,
+                source: Some(item.source),
+            });
+        }
+
+        Ok(DiagnosticsResult { diagnostics })
+    }
+
+    /// Rename the symbol at a position everywhere it is used. With a
+    /// language server configured for the file its `textDocument/rename` is
+    /// used; otherwise every identifier with the same name in the same file
+    /// is renamed.
+    pub fn rename_symbol(&self, args: RenameSymbolArgs) -> Result<RenameSymbolResult> {
+        let action = args.action.unwrap_or(ReplaceAction::Preview);
+        let new_name = args.new_name.trim();
+        // `$` is an identifier character only in JavaScript and TypeScript.
+        let allows_dollar = matches!(
+            Language::for_path(Path::new(&args.path)),
+            Some(Language::JavaScript | Language::TypeScript | Language::Tsx)
+        );
+        let valid = new_name.chars().next().is_some_and(|c| !c.is_ascii_digit())
+            && new_name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || (c == '$' && allows_dollar));
+        if !valid {
+            return Err(FsError::InvalidIdentifier {
+                name: args.new_name.clone(),
+            });
+        }
+
+        // Only a language server knows which occurrences refer to the
+        // symbol, so without one there is no rename (`NoLanguageServer`).
+        let (client, params, line_text) =
+            self.lsp_position(&args.path, args.line, Some(args.column))?;
+        let column = args.column as usize;
+        let old_name = match line_text.get(column..) {
+            Some(rest) if !rest.is_empty() => word_at(&line_text, column),
+            _ => "",
+        };
+        if old_name.is_empty() {
+            return Err(FsError::NoIdentifierAtPosition {
+                path: self.resolve_path(&args.path)?,
+                line: args.line,
+                column: args.column,
+            });
+        }
+        let old_name = old_name.to_string();
+        let changes = self.rename_with_lsp(&client, params, new_name)?;
+
+        let (files, total_replacements) = self.finish_rewrites(changes, action)?;
+        Ok(RenameSymbolResult {
+            old_name,
+            new_name: new_name.to_string(),
+            files,
+            total_replacements,
+            applied: action == ReplaceAction::Apply,
+        })
+    }
+
+    /// Ask the language server for the rename edits at `params` and apply
+    /// them to the affected files in memory.
+    fn rename_with_lsp(
+        &self,
+        client: &LspClient,
+        mut params: serde_json::Value,
+        new_name: &str,
+    ) -> Result<Vec<PendingRewrite>> {
+        params["newName"] = serde_json::Value::String(new_name.to_string());
+        let result = client.request("textDocument/rename", params)?;
+        if result.is_null() {
+            return Err(FsError::RenameRejected {
+                server: client.name().to_string(),
+            });
+        }
+        let Some(edits) = lsp::workspace_edit(&result) else {
+            return Err(FsError::LanguageServer {
+                server: client.name().to_string(),
+                message: "rename needs file operations, which are not supported".to_string(),
+            });
+        };
+
+        let mut changes = Vec::with_capacity(edits.len());
+        for (path, mut edits) in edits {
+            // Library sources outside the root are never rewritten.
+            if !path.starts_with(&self.root) {
+                return Err(FsError::PathEscapesRepo { path });
+            }
+            let bytes = std::fs::read(&path).map_err(|source| FsError::ReadFile {
+                path: path.clone(),
+                source,
+            })?;
+            let Some((encoding, original)) =
+                Self::decode_for_rewrite("rename_symbol", &path, bytes)
+            else {
+                return Err(FsError::FileNotUtf8 { path });
+            };
+
+            let line_starts: Vec<usize> = std::iter::once(0)
+                .chain(original.match_indices('\n').map(|(i, _)| i + 1))
+                .collect();
+            let offset = |position: lsp::Position| {
+                let Some(&start) = line_starts.get(position.line as usize) else {
+                    return original.len();
+                };
+                let line = original[start..].split('\n').next().unwrap_or_default();
+                start + client.to_byte(line, position.character)
+            };
+
+            // Apply from the end so earlier offsets stay valid.
+            edits.sort_by_key(|(range, _)| std::cmp::Reverse(offset(range.start)));
+            let mut replaced = original.clone();
+            for (range, text) in &edits {
+                let (start, end) = (offset(range.start), offset(range.end));
+                replaced.replace_range(start..end.max(start), text);
+            }
+            if replaced == original {
+                continue;
+            }
+
+            let Some(encoded) = encoding.encode(&replaced) else {
+                return Err(FsError::UnencodableContent {
+                    path,
+                    encoding: encoding.name().to_string(),
+                });
+            };
+            changes.push(PendingRewrite {
+                path,
+                original,
+                replaced,
+                encoded,
+                count: edits.len() as u64,
+            });
+        }
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(changes)
+    }
+
+    /// Run an allow-listed command in the server root (or `cwd` below it)
+    /// with a scrubbed environment, a timeout and capped output. Output is
+    /// reported as progress while the command runs; cancelling the request
+    /// kills it.
+    pub fn run_command(
+        &self,
+        args: RunCommandArgs,
+        control: &WalkControl,
+    ) -> Result<RunCommandResult> {
+        let config = &self.config.run_command;
+        if config.allow.is_empty() {
+            return Err(FsError::RunCommandDisabled);
+        }
+        let allowed = config.allow.iter().any(|entry| {
+            let words: Vec<&str> = entry.split_whitespace().collect();
+            !words.is_empty()
+                && args.command.len() >= words.len()
+                && args
+                    .command
+                    .iter()
+                    .zip(&words)
+                    .all(|(arg, word)| arg == word)
         });
+        if !allowed {
+            return Err(FsError::CommandNotAllowed {
+                command: args.command.join(" "),
+                allowed: config.allow.join(", "),
+            });
+        }
 
-        let hits = {
-            let mut guard = hits
-                .lock()
-                .expect("search_text: hits mutex poisoned at final collection");
-            std::mem::take(&mut *guard)
+        let cwd = match args.cwd.as_deref() {
+            Some(cwd) => {
+                let path = self.resolve_path(cwd)?;
+                if !path.is_dir() {
+                    return Err(FsError::CommandCwdNotDirectory { path });
+                }
+                path
+            }
+            None => self.root.clone(),
+        };
+        let max_output_bytes = args
+            .max_output_bytes
+            .unwrap_or(DEFAULT_MAX_COMMAND_OUTPUT)
+            .min(
+                config
+                    .max_output_bytes
+                    .unwrap_or(DEFAULT_MAX_COMMAND_OUTPUT_LIMIT),
+            );
+        let max_timeout = config
+            .max_timeout_ms
+            .unwrap_or(DEFAULT_MAX_COMMAND_TIMEOUT_MS);
+        let timeout = args
+            .timeout_ms
+            .unwrap_or(DEFAULT_COMMAND_TIMEOUT_MS)
+            .min(max_timeout);
+
+        let spec = CommandSpec {
+            argv: &args.command,
+            cwd: &cwd,
+            pass_env: command::DEFAULT_PASS_ENV
+                .iter()
+                .map(|name| name.to_string())
+                .chain(config.pass_env.iter().cloned())
+                .collect(),
+            env: &config.env,
+            timeout: Duration::from_millis(timeout),
+            max_output_bytes: max_output_bytes as usize,
+            deny_network: config.deny_network || args.deny_network.unwrap_or(false),
         };
 
-        Ok(SearchTextResult {
-            hits,
-            has_more: hit_limit.load(Ordering::Relaxed),
+        // Batch output into one progress notification per interval.
+        let mut pending = Vec::new();
+        let mut reported_bytes = 0u64;
+        let mut last_report = Instant::now();
+        let output = command::run(
+            &spec,
+            || control.should_stop(),
+            |chunk| {
+                pending.extend_from_slice(chunk);
+                if last_report.elapsed() >= PROGRESS_INTERVAL {
+                    reported_bytes += pending.len() as u64;
+                    control.report(WalkProgress {
+                        entries_scanned: reported_bytes,
+                        matches: 0,
+                        message: Some(String::from_utf8_lossy(&pending).into_owned()),
+                    });
+                    pending.clear();
+                    last_report = Instant::now();
+                }
+            },
+        )
+        .map_err(|source| FsError::RunCommand {
+            command: args.command.join(" "),
+            source,
+        })?;
+        // The tail of the output (often the summary) is still unreported.
+        if !pending.is_empty() {
+            reported_bytes += pending.len() as u64;
+            control.report(WalkProgress {
+                entries_scanned: reported_bytes,
+                matches: 0,
+                message: Some(String::from_utf8_lossy(&pending).into_owned()),
+            });
+        }
+
+        Ok(RunCommandResult {
+            exit_code: output.exit_code,
+            success: output.success,
+            timed_out: output.timed_out,
+            duration_ms: output.duration.as_millis() as u64,
+            stdout: output.stdout.text(),
+            stderr: output.stderr.text(),
+            stdout_truncated: output.stdout.truncated(),
+            stderr_truncated: output.stderr.truncated(),
+        })
+    }
+
+    /// Open (or refresh) `path` in its language server and build the
+    /// `TextDocumentPositionParams` for a 1-based line and byte column.
+    /// Also returns the text of that line.
+    fn lsp_position(
+        &self,
+        path: &str,
+        line: u64,
+        column: Option<u64>,
+    ) -> Result<(Arc<LspClient>, serde_json::Value, String)> {
+        let abs_path = self.resolve_path(path)?;
+        let client = self.lsp.client_for(&abs_path)?;
+        let (text, _) = self.lsp_sync(&client, &abs_path)?;
+
+        let total_lines = text.lines().count() as u64;
+        let Some(line_text) = line
+            .checked_sub(1)
+            .and_then(|index| text.lines().nth(index as usize))
+        else {
+            return Err(FsError::LineOutOfRange {
+                path: abs_path,
+                line,
+                total_lines,
+            });
+        };
+        let column = match column {
+            Some(column) => column as usize,
+            None => line_text.len() - line_text.trim_start().len(),
+        };
+
+        let params = serde_json::json!({
+            "textDocument": { "uri": lsp::file_uri(&abs_path) },
+            "position": {
+                "line": line - 1,
+                "character": client.to_character(line_text, column)
+            }
+        });
+        let line_text = line_text.to_string();
+        Ok((client, params, line_text))
+    }
+
+    /// Send the current content of `abs_path` to `client` if it changed,
+    /// returning the decoded text and whether anything was sent.
+    fn lsp_sync(&self, client: &LspClient, abs_path: &Path) -> Result<(String, bool)> {
+        let meta = std::fs::metadata(abs_path).map_err(|source| FsError::FileMetadata {
+            path: abs_path.to_path_buf(),
+            source,
+        })?;
+        if !meta.is_file() {
+            return Err(FsError::ReadFileNotFile {
+                path: abs_path.to_path_buf(),
+            });
+        }
+        let bytes = std::fs::read(abs_path).map_err(|source| FsError::ReadFile {
+            path: abs_path.to_path_buf(),
+            source,
+        })?;
+        let Some(text) = content::decode_text(&bytes) else {
+            return Err(FsError::FileNotUtf8 {
+                path: abs_path.to_path_buf(),
+            });
+        };
+        let sent = client.sync_document(abs_path, &text)?;
+        Ok((text, sent))
+    }
+
+    /// Map a definition/references result to root-relative locations with
+    /// byte columns and the text of each location's first line.
+    fn lsp_locations(&self, client: &LspClient, result: &serde_json::Value) -> Vec<LspLocation> {
+        let mut files: HashMap<PathBuf, Vec<String>> = HashMap::new();
+        lsp::locations(result)
+            .into_iter()
+            .map(|(path, range)| {
+                let lines = files.entry(path.clone()).or_insert_with(|| {
+                    std::fs::read(&path)
+                        .ok()
+                        .and_then(|bytes| content::decode_text(&bytes))
+                        .map(|text| text.lines().map(str::to_string).collect())
+                        .unwrap_or_default()
+                });
+                let line = |index: u32| lines.get(index as usize).map_or("", String::as_str);
+
+                LspLocation {
+                    path: self
+                        .strip_root(&path)
+                        .unwrap_or_else(|| path.display().to_string()),
+                    line: range.start.line as u64 + 1,
+                    column: client.to_byte(line(range.start.line), range.start.character) as u64,
+                    end_line: range.end.line as u64 + 1,
+                    end_column: client.to_byte(line(range.end.line), range.end.character) as u64,
+                    line_text: line(range.start.line).to_string(),
+                }
+            })
+            .collect()
+    }
+
+    /// Gather per-file ranking signals for a hit path as reported by
+    /// `search_text` (relative to the server root, or absolute). The line
+    /// count was recorded while searching, so files are not read again.
+    /// Archive members use the archive's modification time.
+    fn rank_file_info(&self, display_path: &str, line_count: Option<u64>) -> FileInfo {
+        let file_path = match self.split_archive_member(display_path) {
+            Some((archive_path, _)) => archive_path,
+            None => display_path,
+        };
+        let path = Path::new(file_path);
+        let full = if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            self.root.join(path)
+        };
+
+        let modified = std::fs::metadata(&full)
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|dur| dur.as_secs());
+
+        FileInfo {
+            line_count: line_count.unwrap_or(1),
+            modified,
+        }
+    }
+
+    pub fn replace_text(
+        &self,
+        args: ReplaceTextArgs,
+        control: &WalkControl,
+    ) -> Result<ReplaceTextResult> {
+        let mode = args.mode.unwrap_or(SearchMode::Literal);
+        let case_sensitive = args.case_sensitive.unwrap_or(false);
+        let action = args.action.unwrap_or(ReplaceAction::Preview);
+        let max_files = args.max_files.unwrap_or(DEFAULT_REPLACE_MAX_FILES);
+
+        let start_path = self.resolve_search_root(args.root.as_deref())?;
+
+        let include_globs = Self::build_globset(&args.include_globs)?;
+        let exclude_globs = Self::build_globset(&args.exclude_globs)?;
+        let walk = WalkOptions::new(
+            args.include_hidden,
+            args.no_ignore,
+            args.no_ignore_vcs,
+            args.follow_symlinks,
+            args.max_depth,
+        )
+        .with_types(self.build_types(&args.types, &args.exclude_types)?);
+
+        let matcher = Self::build_search_matcher(&args.query, mode, case_sensitive)?;
+
+        // Walk sequentially in path order so previews are deterministic and
+        // `max_files` always selects the same set of files.
+        let mut builder = self.walk_builder(&start_path, walk);
+        builder.sort_by_file_path(|a, b| a.cmp(b));
+
+        let mut changes = Vec::new();
+        let mut has_more = false;
+        let mut stopped = false;
+
+        for result in builder.build() {
+            if control.should_stop() {
+                stopped = true;
+                break;
+            }
+            control.tick(changes.len() as u64);
+
+            let entry = match result {
+                Ok(e) => e,
+                Err(err) => {
+                    eprintln!("replace_text: skip entry error: {err}");
+                    continue;
+                }
+            };
+
+            let path = entry.path();
+            if !entry.file_type().map(|t| t.is_file()).unwrap_or(false) {
+                continue;
+            }
+
+            let rel = match path.strip_prefix(&start_path) {
+                Ok(r) => r,
+                Err(_) => path.strip_prefix(&self.root).unwrap_or(path),
+            };
+
+            let rel_str = rel.to_string_lossy();
+
+            if let Some(ref excludes) = exclude_globs
+                && excludes.is_match(rel_str.as_ref())
+            {
+                continue;
+            }
+
+            if let Some(ref includes) = include_globs
+                && !includes.is_match(rel_str.as_ref())
+            {
+                continue;
+            }
+
+            // Binary or unreadable files are skipped rather than risking a
+            // corrupted write. Legacy-encoded text is transcoded to UTF-8 for
+            // matching and written back in its original encoding, as long as
+            // it round-trips losslessly.
+            let bytes = match std::fs::read(path) {
+                Ok(b) => b,
+                Err(err) => {
+                    eprintln!(
+                        "replace_text: skip unreadable file {}: {err}",
+                        path.display()
+                    );
+                    continue;
+                }
+            };
+
+            let Some((encoding, original)) = Self::decode_for_rewrite("replace_text", path, bytes)
+            else {
+                continue;
+            };
+
+            let Some((replaced, count)) =
+                Self::replace_lines(&matcher, &original, mode, &args.replacement)
+            else {
+                continue;
+            };
+
+            if count == 0 || replaced == original {
+                continue;
+            }
+
+            if changes.len() as u32 >= max_files {
+                has_more = true;
+                break;
+            }
+
+            let Some(encoded) = encoding.encode(&replaced) else {
+                eprintln!(
+                    "replace_text: skip file, replacement not representable in {}: {}",
+                    encoding.name(),
+                    path.display()
+                );
+                continue;
+            };
+
+            changes.push(PendingRewrite {
+                path: path.to_path_buf(),
+                original,
+                replaced,
+                encoded,
+                count,
+            });
+        }
+
+        // Like `search_structural`, a walk cut short is only previewed.
+        let action = if stopped {
+            ReplaceAction::Preview
+        } else {
+            action
+        };
+        let (files, total_replacements) = self.finish_rewrites(changes, action)?;
+
+        Ok(ReplaceTextResult {
+            files,
+            total_replacements,
+            applied: action == ReplaceAction::Apply,
+            has_more: has_more || stopped,
+            timed_out: control.timed_out(),
         })
     }
 
+    /// Decode a file that is about to be rewritten. Binary files and
+    /// legacy-encoded text that does not round-trip losslessly are skipped
+    /// (with a note on stderr) rather than risking a corrupted write.
+    fn decode_for_rewrite(
+        tool: &str,
+        path: &Path,
+        bytes: Vec<u8>,
+    ) -> Option<(TextEncoding, String)> {
+        if content::is_binary(&bytes) {
+            return None;
+        }
+
+        let encoding =
+            content::detect_encoding(&bytes[..bytes.len().min(content::ENCODING_SNIFF_LEN)]);
+        if encoding.is_utf8() {
+            return String::from_utf8(bytes).ok().map(|s| (encoding, s));
+        }
+
+        let decoded = encoding.decode(&bytes).into_owned();
+        if encoding.encode(&decoded).as_deref() != Some(bytes.as_slice()) {
+            eprintln!(
+                "{tool}: skip file not round-tripping through {}: {}",
+                encoding.name(),
+                path.display()
+            );
+            return None;
+        }
+        Some((encoding, decoded))
+    }
+
+    /// Apply `matcher` line by line, mirroring the line-based semantics of
+    /// `search_text`, and return the new content with the number of
+    /// replacements made. Returns `None` if the result is not valid UTF-8.
+    fn replace_lines(
+        matcher: &ByteRegex,
+        content: &str,
+        mode: SearchMode,
+        replacement: &str,
+    ) -> Option<(String, u64)> {
+        let mut out = Vec::with_capacity(content.len());
+        let mut count: u64 = 0;
+
+        for line in content.as_bytes().split_inclusive(|&b| b == b'\n') {
+            let (body, newline) = match line.strip_suffix(b"\n") {
+                Some(body) => (body, &b"\n"[..]),
+                None => (line, &b""[..]),
+            };
+
+            let found = matcher.find_iter(body).count() as u64;
+            if found == 0 {
+                out.extend_from_slice(line);
+                continue;
+            }
+            count += found;
+
+            let replaced = match mode {
+                SearchMode::Literal => matcher.replace_all(body, NoExpand(replacement.as_bytes())),
+                SearchMode::Regex => matcher.replace_all(body, replacement.as_bytes()),
+            };
+            out.extend_from_slice(&replaced);
+            out.extend_from_slice(newline);
+        }
+
+        String::from_utf8(out).ok().map(|s| (s, count))
+    }
+
+    /// Preview `changes` as unified diffs or write them atomically, returning
+    /// the per-file summary and the total number of replacements.
+    ///
+    /// When applying, every new file content is written to a temporary file
+    /// first; the targets are only replaced once all of them were written,
+    /// so a failure (permissions, full disk) leaves every file unchanged.
+    fn finish_rewrites(
+        &self,
+        mut changes: Vec<PendingRewrite>,
+        action: ReplaceAction,
+    ) -> Result<(Vec<FileReplacement>, u64)> {
+        // A file reached through a symlink as well is rewritten once.
+        let mut targets = HashSet::new();
+        changes.retain(|change| {
+            targets
+                .insert(std::fs::canonicalize(&change.path).unwrap_or_else(|_| change.path.clone()))
+        });
+
+        if action == ReplaceAction::Apply {
+            // Stage every file with a backup of its current bytes first, so
+            // a failed rename can put the files already committed back.
+            let mut staged = Vec::with_capacity(changes.len());
+            for change in &changes {
+                let write = std::fs::read(&change.path)
+                    .map_err(|source| FsError::ReadFile {
+                        path: change.path.clone(),
+                        source,
+                    })
+                    .and_then(|backup| {
+                        Ok((StagedWrite::new(&change.path, &change.encoded)?, backup))
+                    });
+                match write {
+                    Ok(write) => staged.push(write),
+                    Err(err) => {
+                        staged.iter().for_each(|(write, _)| write.discard());
+                        return Err(err);
+                    }
+                }
+            }
+            let mut committed = Vec::with_capacity(staged.len());
+            let mut staged = staged.into_iter();
+            while let Some((write, backup)) = staged.next() {
+                let target = write.target.clone();
+                if let Err(err) = write.commit() {
+                    staged.for_each(|(write, _)| write.discard());
+                    let modified: Vec<String> = committed
+                        .into_iter()
+                        .filter(|(target, backup): &(PathBuf, Vec<u8>)| {
+                            StagedWrite::new(target, backup)
+                                .and_then(StagedWrite::commit)
+                                .is_err()
+                        })
+                        .map(|(target, _)| target.display().to_string())
+                        .collect();
+                    if modified.is_empty() {
+                        return Err(err);
+                    }
+                    return Err(FsError::RewriteNotRolledBack {
+                        error: Box::new(err),
+                        modified: modified.join(", "),
+                    });
+                }
+                committed.push((target, backup));
+            }
+        }
+
+        let mut files = Vec::with_capacity(changes.len());
+        let mut total_replacements = 0;
+        for change in changes {
+            let display_path = self
+                .strip_root(&change.path)
+                .unwrap_or_else(|| change.path.display().to_string());
+            let diff = (action == ReplaceAction::Preview)
+                .then(|| Self::unified_diff(&change.original, &change.replaced, &display_path));
+
+            total_replacements += change.count;
+            files.push(FileReplacement {
+                path: display_path,
+                replacements: change.count,
+                diff,
+            });
+        }
+
+        Ok((files, total_replacements))
+    }
+
+    fn unified_diff(old: &str, new: &str, display_path: &str) -> String {
+        TextDiff::from_lines(old, new)
+            .unified_diff()
+            .context_radius(DIFF_CONTEXT_LINES)
+            .header(&format!("a/{display_path}"), &format!("b/{display_path}"))
+            .to_string()
+    }
+
     fn strip_root(&self, path: &Path) -> Option<String> {
         path.strip_prefix(&self.root)
             .ok()
             .map(|p| p.to_string_lossy().into_owned())
     }
 }
+
+/// `path` with `.` and `..` components resolved without touching the file
+/// system, so paths of deleted files can still be shown relative to the root.
+fn normalize_lexically(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// The identifier-like word (letters, digits, `_`, `$`) around byte `column`.
+fn word_at(line: &str, column: usize) -> &str {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '$';
+    let mut column = column.min(line.len());
+    while !line.is_char_boundary(column) {
+        column -= 1;
+    }
+    let start = line[..column]
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| is_word(c))
+        .last()
+        .map_or(column, |(i, _)| i);
+    let end = line[column..]
+        .char_indices()
+        .find(|&(_, c)| !is_word(c))
+        .map_or(line.len(), |(i, _)| column + i);
+    &line[start..end]
+}
@@ -0,0 +1,276 @@
+//! Process execution for `run_command`: a scrubbed environment, a deadline,
+//! capped output and optional network isolation.
+
+use std::collections::BTreeMap;
+use std::io::{self, Read};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// How often the output loop wakes up to check for cancellation.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How long to keep reading after killing the process, in case a
+/// descendant outside its process group still holds the pipes open.
+const KILL_GRACE: Duration = Duration::from_secs(1);
+const READ_CHUNK: usize = 8 * 1024;
+
+/// Environment variables passed through to commands unless scrubbed by
+/// omission from this list (plus `run_command.pass_env`).
+pub const DEFAULT_PASS_ENV: &[&str] = &[
+    "PATH",
+    "HOME",
+    "USER",
+    "LANG",
+    "LC_ALL",
+    "TMPDIR",
+    "CARGO_HOME",
+    "RUSTUP_HOME",
+    "RUSTUP_TOOLCHAIN",
+    "GOPATH",
+    "GOROOT",
+    "GOCACHE",
+    "JAVA_HOME",
+];
+
+/// What to run and under which limits.
+pub struct CommandSpec<'a> {
+    pub argv: &'a [String],
+    pub cwd: &'a Path,
+    /// Variables copied from this process's environment.
+    pub pass_env: Vec<String>,
+    /// Variables set explicitly, overriding passed ones.
+    pub env: &'a BTreeMap<String, String>,
+    pub timeout: Duration,
+    /// Bytes of each stream kept; half from the start, half from the end.
+    pub max_output_bytes: usize,
+    pub deny_network: bool,
+}
+
+pub struct CommandOutput {
+    pub exit_code: Option<i32>,
+    pub success: bool,
+    pub timed_out: bool,
+    pub duration: Duration,
+    pub stdout: CappedOutput,
+    pub stderr: CappedOutput,
+}
+
+/// The start and the end of a stream, dropping the middle once it grows
+/// past the cap.
+pub struct CappedOutput {
+    cap: usize,
+    head: Vec<u8>,
+    tail: Vec<u8>,
+    total: usize,
+}
+
+impl CappedOutput {
+    fn new(cap: usize) -> Self {
+        Self {
+            cap,
+            head: Vec::new(),
+            tail: Vec::new(),
+            total: 0,
+        }
+    }
+
+    fn push(&mut self, mut bytes: &[u8]) {
+        self.total += bytes.len();
+        let head_room = (self.cap / 2).saturating_sub(self.head.len());
+        if head_room > 0 {
+            let take = head_room.min(bytes.len());
+            self.head.extend_from_slice(&bytes[..take]);
+            bytes = &bytes[take..];
+        }
+        self.tail.extend_from_slice(bytes);
+        let tail_cap = self.cap - self.cap / 2;
+        // Trim in batches rather than on every chunk.
+        if self.tail.len() > tail_cap * 2 {
+            self.tail.drain(..self.tail.len() - tail_cap);
+        }
+    }
+
+    pub fn truncated(&self) -> bool {
+        self.total > self.cap
+    }
+
+    /// The kept output as text, with a marker where bytes were dropped.
+    pub fn text(&self) -> String {
+        let tail_cap = self.cap - self.cap / 2;
+        let tail = &self.tail[self.tail.len().saturating_sub(tail_cap)..];
+        if !self.truncated() {
+            let mut all = self.head.clone();
+            all.extend_from_slice(tail);
+            return String::from_utf8_lossy(&all).into_owned();
+        }
+        let omitted = self.total - self.head.len() - tail.len();
+        format!(
+            "{}\n... [{omitted} bytes omitted] ...\n{}",
+            String::from_utf8_lossy(&self.head),
+            String::from_utf8_lossy(tail)
+        )
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Run `spec` to completion, the deadline or until `should_stop` returns
+/// true, calling `on_output` with each chunk of output as it arrives.
+pub fn run(
+    spec: &CommandSpec<'_>,
+    should_stop: impl Fn() -> bool,
+    mut on_output: impl FnMut(&[u8]),
+) -> io::Result<CommandOutput> {
+    let started = Instant::now();
+    let mut child = spawn(spec)?;
+
+    let (tx, rx) = mpsc::channel::<(Stream, Vec<u8>)>();
+    if let Some(stdout) = child.stdout.take() {
+        forward(stdout, Stream::Stdout, tx.clone());
+    }
+    if let Some(stderr) = child.stderr.take() {
+        forward(stderr, Stream::Stderr, tx.clone());
+    }
+    drop(tx);
+
+    let mut stdout = CappedOutput::new(spec.max_output_bytes);
+    let mut stderr = CappedOutput::new(spec.max_output_bytes);
+    let mut timed_out = false;
+    let mut cancelled = false;
+    let mut killed_at: Option<Instant> = None;
+    let mut pipes_open = true;
+
+    let status = loop {
+        if pipes_open {
+            match rx.recv_timeout(POLL_INTERVAL) {
+                Ok((stream, chunk)) => {
+                    match stream {
+                        Stream::Stdout => stdout.push(&chunk),
+                        Stream::Stderr => stderr.push(&chunk),
+                    }
+                    on_output(&chunk);
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => pipes_open = false,
+            }
+        } else if let Some(status) = child.try_wait()? {
+            break status;
+        } else {
+            // The process closed its output but keeps running, so it is
+            // still subject to the deadline and cancellation.
+            std::thread::sleep(POLL_INTERVAL);
+        }
+
+        match killed_at {
+            Some(at) if at.elapsed() >= KILL_GRACE => break child.wait()?,
+            Some(_) => {}
+            None => {
+                if started.elapsed() >= spec.timeout {
+                    timed_out = true;
+                } else if should_stop() {
+                    cancelled = true;
+                } else {
+                    continue;
+                }
+                kill(&mut child);
+                killed_at = Some(Instant::now());
+            }
+        }
+    };
+
+    Ok(CommandOutput {
+        exit_code: status.code(),
+        success: status.success() && !timed_out && !cancelled,
+        timed_out,
+        duration: started.elapsed(),
+        stdout,
+        stderr,
+    })
+}
+
+fn spawn(spec: &CommandSpec<'_>) -> io::Result<Child> {
+    let (program, args) = spec
+        .argv
+        .split_first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty command"))?;
+
+    let mut command = if spec.deny_network {
+        network_isolated(program)?
+    } else {
+        Command::new(program)
+    };
+    command
+        .args(args)
+        .current_dir(spec.cwd)
+        .env_clear()
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    for name in &spec.pass_env {
+        if let Some(value) = std::env::var_os(name) {
+            command.env(name, value);
+        }
+    }
+    command.envs(spec.env);
+
+    // A process group of its own lets a timeout kill the whole tree
+    // (e.g. `cargo` and the `rustc` processes it spawned).
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+
+    command.spawn()
+}
+
+/// Run `program` in new user and network namespaces, which only have a
+/// loopback interface.
+#[cfg(target_os = "linux")]
+fn network_isolated(program: &str) -> io::Result<Command> {
+    let mut command = Command::new("unshare");
+    command.args(["--user", "--map-root-user", "--net", "--", program]);
+    Ok(command)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn network_isolated(_program: &str) -> io::Result<Command> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "network isolation is only available on Linux",
+    ))
+}
+
+fn forward(
+    mut reader: impl Read + Send + 'static,
+    stream: Stream,
+    tx: mpsc::Sender<(Stream, Vec<u8>)>,
+) {
+    std::thread::spawn(move || {
+        let mut buf = vec![0; READ_CHUNK];
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if tx.send((stream, buf[..n].to_vec())).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+fn kill(child: &mut Child) {
+    #[cfg(unix)]
+    {
+        // SAFETY: `kill` has no memory safety requirements; a negative pid
+        // addresses the process group created in `spawn`.
+        unsafe {
+            libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+        }
+    }
+    let _ = child.kill();
+}
@@ -0,0 +1,264 @@
+//! Parsing of compiler and linter output into diagnostics: cargo/rustc JSON
+//! messages, `tsc`, `eslint --format json` and the `file:line:col: severity:
+//! message` lines printed by gcc, clang, go, mypy, javac and many others.
+
+use std::sync::LazyLock;
+
+use regex::Regex;
+use serde_json::Value;
+
+use crate::types::{DiagnosticSeverity, DiagnosticsFormat};
+
+static ANSI_ESCAPE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\x1b\[[0-9;]*[A-Za-z]").expect("valid ANSI escape regex"));
+
+/// `src/a.ts(12,5): error TS2322: ...` and, with `--pretty`,
+/// `src/a.ts:12:5 - error TS2322: ...`.
+static TSC_LINE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(?:(?P<path>[^\s(][^(]*)\((?P<line>\d+),(?P<col>\d+)\):|(?P<pretty_path>\S+):(?P<pretty_line>\d+):(?P<pretty_col>\d+) -) (?P<sev>error|warning|message) (?P<code>TS\d+): (?P<msg>.*)$",
+    )
+    .expect("valid tsc regex")
+});
+
+/// `path:line[:col]: [severity:] message`.
+static GCC_LINE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(?P<path>[^\s:]+):(?P<line>\d+):(?:(?P<col>\d+):)?\s*(?:(?P<sev>fatal error|error|warning|note|info|remark)\s*:\s*)?(?P<msg>\S.*)$",
+    )
+    .expect("valid gcc regex")
+});
+
+/// A trailing `[code]` as printed by gcc (`[-Wunused-variable]`) and mypy
+/// (`[arg-type]`).
+static TRAILING_CODE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\s+\[([^\]\s]+)\]$").expect("valid trailing code regex"));
+
+/// What a column number counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnUnit {
+    Bytes,
+    Chars,
+    Utf16,
+}
+
+/// One diagnostic as the tool reported it: the path is as printed and
+/// columns are 1-based in the tool's own unit.
+#[derive(Debug)]
+pub struct Reported {
+    pub path: String,
+    pub line: u64,
+    /// 1-based; 0 when the tool gave no column.
+    pub column: u64,
+    pub end: Option<(u64, u64)>,
+    pub unit: ColumnUnit,
+    pub severity: DiagnosticSeverity,
+    pub code: Option<String>,
+    pub message: String,
+    pub source: String,
+}
+
+/// Parse `output` as `format`. With [`DiagnosticsFormat::Auto`] eslint's
+/// JSON array is recognized as a whole and every other line is tried as a
+/// cargo JSON message, a `tsc` line and a gcc-style line in that order.
+/// Lines that are not diagnostics (progress, test output, code excerpts)
+/// are skipped.
+pub fn parse(output: &str, format: DiagnosticsFormat) -> Result<Vec<Reported>, serde_json::Error> {
+    if format == DiagnosticsFormat::Eslint {
+        return eslint(output);
+    }
+    if format == DiagnosticsFormat::Auto
+        && output.trim_start().starts_with('[')
+        && let Ok(reported) = eslint(output)
+    {
+        return Ok(reported);
+    }
+
+    let output = ANSI_ESCAPE.replace_all(output, "");
+    let mut reported = Vec::new();
+    for line in output.lines() {
+        let line = line.trim_end();
+        let parsed = match format {
+            DiagnosticsFormat::Cargo => cargo_line(line),
+            DiagnosticsFormat::Tsc => tsc_line(line),
+            DiagnosticsFormat::Gcc => gcc_line(line),
+            DiagnosticsFormat::Auto | DiagnosticsFormat::Eslint => cargo_line(line)
+                .or_else(|| tsc_line(line))
+                .or_else(|| gcc_line(line)),
+        };
+        reported.extend(parsed);
+    }
+    Ok(reported)
+}
+
+/// Byte offset (0-based) of the 1-based `column`, counted in `unit`, within
+/// `line`.
+pub fn byte_column(line: &str, column: u64, unit: ColumnUnit) -> u64 {
+    let wanted = column.saturating_sub(1) as usize;
+    let byte = match unit {
+        ColumnUnit::Bytes => {
+            let mut byte = wanted.min(line.len());
+            while !line.is_char_boundary(byte) {
+                byte -= 1;
+            }
+            byte
+        }
+        ColumnUnit::Chars => line
+            .char_indices()
+            .nth(wanted)
+            .map_or(line.len(), |(byte, _)| byte),
+        ColumnUnit::Utf16 => {
+            let mut units = 0;
+            line.char_indices()
+                .find(|(_, c)| {
+                    let reached = units >= wanted;
+                    units += c.len_utf16();
+                    reached
+                })
+                .map_or(line.len(), |(byte, _)| byte)
+        }
+    };
+    byte as u64
+}
+
+/// A `cargo --message-format=json` line (`"reason": "compiler-message"`) or
+/// a bare `rustc --error-format=json` message, located at its primary span.
+fn cargo_line(line: &str) -> Option<Reported> {
+    if !line.starts_with('{') {
+        return None;
+    }
+    let value: Value = serde_json::from_str(line).ok()?;
+    let message = match value.get("reason").and_then(Value::as_str) {
+        Some("compiler-message") => value.get("message")?,
+        Some(_) => return None,
+        None => &value,
+    };
+    // Summaries such as "aborting due to 2 previous errors" have no span.
+    let span = message
+        .get("spans")?
+        .as_array()?
+        .iter()
+        .find(|span| span.get("is_primary").and_then(Value::as_bool) == Some(true))?;
+    let number = |key: &str| span.get(key).and_then(Value::as_u64);
+
+    let code = message
+        .get("code")
+        .and_then(|code| code.get("code"))
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    let source = match &code {
+        Some(code) if code.starts_with("clippy::") => "clippy",
+        _ => "rustc",
+    };
+    let severity = match message.get("level").and_then(Value::as_str)? {
+        "warning" => DiagnosticSeverity::Warning,
+        "note" | "failure-note" => DiagnosticSeverity::Info,
+        "help" => DiagnosticSeverity::Hint,
+        _ => DiagnosticSeverity::Error,
+    };
+    Some(Reported {
+        path: span.get("file_name")?.as_str()?.to_string(),
+        line: number("line_start")?,
+        column: number("column_start").unwrap_or(0),
+        end: number("line_end").zip(number("column_end")),
+        unit: ColumnUnit::Chars,
+        severity,
+        code,
+        message: message.get("message")?.as_str()?.to_string(),
+        source: source.to_string(),
+    })
+}
+
+fn tsc_line(line: &str) -> Option<Reported> {
+    let caps = TSC_LINE.captures(line)?;
+    let group = |plain: &str, pretty: &str| caps.name(plain).or_else(|| caps.name(pretty));
+    let severity = match &caps["sev"] {
+        "error" => DiagnosticSeverity::Error,
+        "warning" => DiagnosticSeverity::Warning,
+        _ => DiagnosticSeverity::Info,
+    };
+    Some(Reported {
+        path: group("path", "pretty_path")?.as_str().trim().to_string(),
+        line: group("line", "pretty_line")?.as_str().parse().ok()?,
+        column: group("col", "pretty_col")?.as_str().parse().ok()?,
+        end: None,
+        unit: ColumnUnit::Utf16,
+        severity,
+        code: Some(caps["code"].to_string()),
+        message: caps["msg"].to_string(),
+        source: "tsc".to_string(),
+    })
+}
+
+fn gcc_line(line: &str) -> Option<Reported> {
+    let caps = GCC_LINE.captures(line)?;
+    // Without a column or a severity too much ordinary output matches
+    // (`Listening on localhost:8080: ...`).
+    if caps.name("col").is_none() && caps.name("sev").is_none() {
+        return None;
+    }
+    let severity = match caps.name("sev").map(|m| m.as_str()) {
+        Some("warning") => DiagnosticSeverity::Warning,
+        Some("note" | "info" | "remark") => DiagnosticSeverity::Info,
+        _ => DiagnosticSeverity::Error,
+    };
+    let mut message = caps["msg"].to_string();
+    let code = TRAILING_CODE
+        .captures(&message)
+        .and_then(|code| Some((code.get(0)?.start(), code[1].to_string())));
+    if let Some((start, _)) = code {
+        message.truncate(start);
+    }
+    let code = code.map(|(_, code)| code);
+    Some(Reported {
+        path: caps["path"].to_string(),
+        line: caps["line"].parse().ok()?,
+        column: caps
+            .name("col")
+            .and_then(|m| m.as_str().parse().ok())
+            .unwrap_or(0),
+        end: None,
+        unit: ColumnUnit::Bytes,
+        severity,
+        code,
+        message,
+        source: "compiler".to_string(),
+    })
+}
+
+/// `eslint --format json`: an array of `{filePath, messages: [...]}`.
+fn eslint(output: &str) -> Result<Vec<Reported>, serde_json::Error> {
+    let files: Vec<Value> = serde_json::from_str(output.trim())?;
+    let mut reported = Vec::new();
+    for file in &files {
+        let Some(path) = file.get("filePath").and_then(Value::as_str) else {
+            continue;
+        };
+        let messages = file.get("messages").and_then(Value::as_array);
+        for message in messages.into_iter().flatten() {
+            let number = |key: &str| message.get(key).and_then(Value::as_u64);
+            let Some(text) = message.get("message").and_then(Value::as_str) else {
+                continue;
+            };
+            let severity = match number("severity") {
+                Some(1) => DiagnosticSeverity::Warning,
+                _ => DiagnosticSeverity::Error,
+            };
+            reported.push(Reported {
+                path: path.to_string(),
+                line: number("line").unwrap_or(1),
+                column: number("column").unwrap_or(0),
+                end: number("endLine").zip(number("endColumn")),
+                unit: ColumnUnit::Utf16,
+                severity,
+                code: message
+                    .get("ruleId")
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+                message: text.to_string(),
+                source: "eslint".to_string(),
+            });
+        }
+    }
+    Ok(reported)
+}
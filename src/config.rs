@@ -0,0 +1,103 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::error::{FsError, Result};
+
+/// Server configuration, loaded from the JSON file passed with `--config`.
+///
+/// Every field is optional; a missing config file means all defaults.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// Custom file type definitions used by the `types` / `exclude_types`
+    /// filters, e.g. `{"proto": ["*.proto"]}`.
+    ///
+    /// Names matching a built-in type extend it with the extra globs.
+    pub file_types: BTreeMap<String, Vec<String>>,
+
+    /// Language servers used by `goto_definition`, `find_references`,
+    /// `hover` and `diagnostics`, keyed by language name (`"rust"`,
+    /// `"python"`, ...) unless `extensions` says which files they serve.
+    pub language_servers: BTreeMap<String, LanguageServerConfig>,
+
+    /// Commands `run_command` may execute. Without an `allow` list the tool
+    /// is disabled.
+    pub run_command: RunCommandConfig,
+
+    /// Formatting of content written by `create_file` and `overwrite_file`.
+    pub format_on_write: FormatOnWriteConfig,
+}
+
+/// Settings for formatting on write.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct FormatOnWriteConfig {
+    /// Format writes by default; a call overrides this with `format`.
+    pub enabled: bool,
+    /// How long a formatter may run. Default: 10000.
+    pub timeout_ms: Option<u64>,
+    /// Formatter commands by file extension, replacing the built-in choice,
+    /// e.g. `{"js": ["biome", "format", "--stdin-file-path", "{path}"]}`.
+    /// They read the content on stdin and print the result; `{path}` is
+    /// replaced by the file's path. Only these commands may name programs
+    /// inside the project; built-in formatters always come from `PATH`.
+    pub formatters: BTreeMap<String, Vec<String>>,
+}
+
+/// Settings for `run_command`.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct RunCommandConfig {
+    /// Allowed command prefixes, e.g. `["cargo test", "npm test"]`. A
+    /// command runs only if its leading arguments equal the words of one
+    /// entry.
+    pub allow: Vec<String>,
+    /// Environment variables passed through in addition to the defaults
+    /// (`PATH`, `HOME`, `LANG`, toolchain homes, ...). Everything else is
+    /// removed.
+    pub pass_env: Vec<String>,
+    /// Environment variables set for every command.
+    pub env: BTreeMap<String, String>,
+    /// Run every command without network access (Linux only).
+    pub deny_network: bool,
+    /// Upper bound for the per-call `timeout_ms`. Default: 600000.
+    pub max_timeout_ms: Option<u64>,
+    /// Upper bound for the per-call `max_output_bytes`. Default: 1048576.
+    pub max_output_bytes: Option<u64>,
+}
+
+/// How to start one language server.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LanguageServerConfig {
+    /// Executable to run, e.g. `"rust-analyzer"`.
+    pub command: String,
+    /// Arguments, e.g. `["--stdio"]`.
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// File extensions handled by the server. Default: the extensions of
+    /// the built-in language with the same name as the entry.
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    /// `initializationOptions` sent with the `initialize` request.
+    #[serde(default)]
+    pub initialization_options: Option<serde_json::Value>,
+    /// How long to wait for each response. Default: 30000.
+    #[serde(default)]
+    pub request_timeout_ms: Option<u64>,
+}
+
+impl ServerConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = std::fs::read_to_string(path).map_err(|source| FsError::ReadConfig {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        serde_json::from_str(&raw).map_err(|source| FsError::ParseConfig {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+}
@@ -0,0 +1,405 @@
+//! Content sniffing and formatting helpers shared by the read, search and
+//! write paths.
+
+use std::borrow::Cow;
+use std::fmt::Write as _;
+
+use chardetng::EncodingDetector;
+use encoding_rs::{
+    BIG5, EUC_JP, EUC_KR, Encoding, GB18030, GBK, SHIFT_JIS, UTF_8, UTF_16BE, UTF_16LE,
+};
+
+/// Number of leading bytes inspected when classifying content.
+const SNIFF_LEN: usize = 8 * 1024;
+
+/// Fraction of non-text control bytes above which content is binary.
+const MAX_CONTROL_RATIO: f64 = 0.1;
+
+const UTF16_LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16_BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+/// Mime type of PDF documents, returned as embedded resources.
+pub const PDF_MIME: &str = "application/pdf";
+
+/// Number of leading bytes used to detect the text encoding of a file.
+pub const ENCODING_SNIFF_LEN: usize = 64 * 1024;
+
+/// Heuristically decide whether `bytes` is binary content.
+///
+/// Like grep and ripgrep, a NUL byte in the leading window marks content as
+/// binary, except after a UTF-16 BOM where NULs are expected. Otherwise a
+/// high ratio of control characters that never appear in text also counts.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    let window = &bytes[..bytes.len().min(SNIFF_LEN)];
+    if window.is_empty() {
+        return false;
+    }
+
+    if window.starts_with(UTF16_LE_BOM) || window.starts_with(UTF16_BE_BOM) {
+        return false;
+    }
+
+    if window.contains(&0) {
+        return true;
+    }
+
+    let control = window
+        .iter()
+        .filter(|&&b| matches!(b, 0x01..=0x08 | 0x0E..=0x1A | 0x1C..=0x1F | 0x7F))
+        .count();
+
+    control as f64 / window.len() as f64 > MAX_CONTROL_RATIO
+}
+
+/// Detect images and PDFs that can be returned to MCP clients as media
+/// content, by their magic bytes.
+pub fn sniff_media_mime(bytes: &[u8]) -> Option<&'static str> {
+    let mime = if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        "image/png"
+    } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        "image/jpeg"
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        "image/gif"
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        "image/webp"
+    } else if bytes.starts_with(b"BM") && bytes.len() >= 14 && bytes[6..10] == [0; 4] {
+        "image/bmp"
+    } else if bytes.starts_with(b"%PDF-") {
+        PDF_MIME
+    } else {
+        return None;
+    };
+    Some(mime)
+}
+
+/// Text encoding of some content, plus whether it started with a byte order
+/// mark that must be written back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextEncoding {
+    pub encoding: &'static Encoding,
+    pub bom: bool,
+}
+
+impl TextEncoding {
+    pub const UTF8: Self = Self {
+        encoding: UTF_8,
+        bom: false,
+    };
+
+    /// WHATWG name of the encoding, e.g. `"UTF-8"`, `"Shift_JIS"`.
+    pub fn name(&self) -> &'static str {
+        self.encoding.name()
+    }
+
+    pub fn is_utf8(&self) -> bool {
+        self.encoding == UTF_8
+    }
+
+    /// Decode `bytes` to UTF-8, dropping a leading BOM. Malformed sequences
+    /// become U+FFFD.
+    pub fn decode<'a>(&self, bytes: &'a [u8]) -> Cow<'a, str> {
+        let bytes = match Encoding::for_bom(bytes) {
+            Some((enc, len)) if enc == self.encoding => &bytes[len..],
+            _ => bytes,
+        };
+        self.encoding.decode_without_bom_handling(bytes).0
+    }
+
+    /// Encode UTF-8 `text` back into this encoding, restoring the BOM if the
+    /// original had one and `text` does not start with it already. Returns
+    /// `None` if `text` contains characters the encoding cannot represent.
+    pub fn encode(&self, text: &str) -> Option<Vec<u8>> {
+        let mut out = Vec::with_capacity(text.len() + 3);
+        if self.bom && !text.starts_with('\u{FEFF}') {
+            out.extend_from_slice(match self.encoding {
+                e if e == UTF_16LE => UTF16_LE_BOM,
+                e if e == UTF_16BE => UTF16_BE_BOM,
+                _ => b"\xEF\xBB\xBF",
+            });
+        }
+
+        // encoding_rs only decodes UTF-16, so encode it by hand.
+        if self.encoding == UTF_16LE || self.encoding == UTF_16BE {
+            let little = self.encoding == UTF_16LE;
+            for unit in text.encode_utf16() {
+                out.extend_from_slice(&if little {
+                    unit.to_le_bytes()
+                } else {
+                    unit.to_be_bytes()
+                });
+            }
+            return Some(out);
+        }
+
+        let (bytes, _, unmappable) = self.encoding.encode(text);
+        if unmappable {
+            return None;
+        }
+        out.extend_from_slice(&bytes);
+        Some(out)
+    }
+}
+
+/// Extra bytes to read past a byte window so [`snap_to_chars`] can skip a
+/// partial character at its start and still complete one at its end.
+pub const SNAP_SLACK: usize = 7;
+
+/// Bytes before a byte window that [`snap_to_chars`] looks at to find
+/// where characters start in legacy multi-byte encodings.
+pub const SNAP_LEAD_IN: usize = 1024;
+
+/// Pick the part of `buf` (read at file offset `offset`, up to `max_len`
+/// bytes plus [`SNAP_SLACK`]) that starts and ends on character boundaries
+/// of `encoding`, returning `start..end` indices into `buf`. `before` holds
+/// up to [`SNAP_LEAD_IN`] bytes preceding `offset`; it is only needed for
+/// legacy multi-byte encodings, whose trail bytes look like lead bytes.
+///
+/// A partial character at the start is skipped and one at the end is left
+/// for the next read. If not even one character fits in `max_len`, the
+/// first character is returned whole so paging always makes progress.
+/// Stateful encodings such as ISO-2022-JP are not snapped.
+pub fn snap_to_chars(
+    buf: &[u8],
+    before: &[u8],
+    offset: u64,
+    max_len: usize,
+    encoding: TextEncoding,
+) -> (usize, usize) {
+    let window = max_len.min(buf.len());
+
+    if encoding.is_utf8() {
+        let is_continuation = |i: usize| buf.get(i).is_some_and(|b| b & 0xC0 == 0x80);
+        let mut start = 0;
+        if offset > 0 {
+            while start < 3 && is_continuation(start) {
+                start += 1;
+            }
+        }
+
+        let mut end = window.max(start);
+        if end < buf.len() {
+            while end > start && is_continuation(end) {
+                end -= 1;
+            }
+            if end == start {
+                end = (start + 1).min(buf.len());
+                while is_continuation(end) {
+                    end += 1;
+                }
+            }
+        }
+        return (start, end);
+    }
+
+    if encoding.encoding == UTF_16LE || encoding.encoding == UTF_16BE {
+        let unit_at = |i: usize| {
+            let pair = [*buf.get(i)?, *buf.get(i + 1)?];
+            Some(if encoding.encoding == UTF_16LE {
+                u16::from_le_bytes(pair)
+            } else {
+                u16::from_be_bytes(pair)
+            })
+        };
+        let is_high = |i: usize| unit_at(i).is_some_and(|u| (0xD800..0xDC00).contains(&u));
+        let is_low = |i: usize| unit_at(i).is_some_and(|u| (0xDC00..0xE000).contains(&u));
+
+        let mut start = (offset % 2) as usize;
+        if offset > 0 && is_low(start) {
+            start += 2;
+        }
+
+        let mut end = start + window.saturating_sub(start) / 2 * 2;
+        if end < buf.len() && end >= start + 2 && is_high(end - 2) {
+            end -= 2;
+        }
+        if end <= start {
+            end = start + if is_high(start) { 4 } else { 2 };
+        }
+        return (start.min(buf.len()), end.min(buf.len()));
+    }
+
+    if is_legacy_multibyte(encoding.encoding) {
+        let char_len = |bytes: &[u8], i: usize| legacy_char_len(encoding.encoding, bytes, i);
+
+        // Bytes below 0x30 are never part of a multi-byte character, so
+        // characters start right after the last one (or at the start of the
+        // file); walk from there.
+        let mut start = 0;
+        if offset > 0 {
+            let fallback = if before.len() as u64 == offset {
+                0
+            } else {
+                before.len()
+            };
+            let sync = before
+                .iter()
+                .rposition(|&b| b < 0x30)
+                .map_or(fallback, |i| i + 1);
+            let lead_in = &before[sync..];
+            let mut joined = lead_in.to_vec();
+            joined.extend_from_slice(&buf[..buf.len().min(3)]);
+            let mut i = 0;
+            while i < lead_in.len() {
+                i += char_len(&joined, i);
+            }
+            start = (i - lead_in.len()).min(buf.len());
+        }
+
+        let mut end = start;
+        while end < buf.len() {
+            let next = end + char_len(buf, end);
+            if next > window && end > start {
+                break;
+            }
+            end = next.min(buf.len());
+        }
+        return (start, end);
+    }
+
+    (0, window)
+}
+
+fn is_legacy_multibyte(encoding: &'static Encoding) -> bool {
+    [SHIFT_JIS, EUC_JP, EUC_KR, BIG5, GBK, GB18030].contains(&encoding)
+}
+
+/// Length of the character starting at `bytes[i]` in a legacy multi-byte
+/// encoding, judged from its lead byte (and, for GB18030, its second byte).
+fn legacy_char_len(encoding: &'static Encoding, bytes: &[u8], i: usize) -> usize {
+    let lead = bytes[i];
+    if lead < 0x80 {
+        return 1;
+    }
+    if encoding == SHIFT_JIS {
+        return if matches!(lead, 0x81..=0x9F | 0xE0..=0xFC) {
+            2
+        } else {
+            1
+        };
+    }
+    if encoding == EUC_JP {
+        return match lead {
+            0x8F => 3,
+            0x8E | 0xA1..=0xFE => 2,
+            _ => 1,
+        };
+    }
+    if encoding == GBK || encoding == GB18030 {
+        return match (lead, bytes.get(i + 1)) {
+            (0x81..=0xFE, Some(0x30..=0x39)) => 4,
+            (0x81..=0xFE, _) => 2,
+            _ => 1,
+        };
+    }
+    // EUC-KR and Big5.
+    if matches!(lead, 0x81..=0xFE) { 2 } else { 1 }
+}
+
+/// Detect the text encoding of `bytes` (typically the first
+/// [`ENCODING_SNIFF_LEN`] bytes of a file).
+///
+/// A BOM wins; otherwise valid UTF-8 is UTF-8 (a sequence cut off at the end
+/// of the window is tolerated), as is UTF-8 with a few stray invalid bytes
+/// among more valid multi-byte characters. Anything else is guessed
+/// statistically.
+pub fn detect_encoding(bytes: &[u8]) -> TextEncoding {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return TextEncoding {
+            encoding,
+            bom: true,
+        };
+    }
+
+    match std::str::from_utf8(bytes) {
+        Ok(_) => return TextEncoding::UTF8,
+        Err(err) if err.error_len().is_none() => return TextEncoding::UTF8,
+        Err(_) if is_mostly_utf8(bytes) => return TextEncoding::UTF8,
+        Err(_) => {}
+    }
+
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    TextEncoding {
+        encoding: detector.guess(None, true),
+        bom: false,
+    }
+}
+
+/// Whether `bytes` has at least as many valid multi-byte UTF-8 characters as
+/// invalid sequences. Legacy encodings rarely form valid UTF-8 sequences, so
+/// this holds for UTF-8 text with stray bytes rather than for legacy text.
+fn is_mostly_utf8(bytes: &[u8]) -> bool {
+    let (mut multibyte, mut invalid) = (0, 0);
+    for chunk in bytes.utf8_chunks() {
+        multibyte += chunk.valid().chars().filter(|c| !c.is_ascii()).count();
+        if !chunk.invalid().is_empty() {
+            invalid += 1;
+        }
+    }
+    multibyte > 0 && multibyte >= invalid
+}
+
+/// Decode UTF-8 `bytes`, replacing invalid sequences with U+FFFD. The flag
+/// is set if any were replaced.
+pub fn decode_utf8_lossy(bytes: Vec<u8>) -> (String, bool) {
+    match String::from_utf8(bytes) {
+        Ok(text) => (text, false),
+        Err(err) => (String::from_utf8_lossy(err.as_bytes()).into_owned(), true),
+    }
+}
+
+/// Name reported for a UTF-8 file whose invalid bytes were replaced.
+pub const UTF8_LOSSY_NAME: &str = "UTF-8 (lossy)";
+
+/// Decode a whole text file to UTF-8 using the encoding detected from its
+/// start. Returns `None` for binary content.
+pub fn decode_text(bytes: &[u8]) -> Option<String> {
+    let head = &bytes[..bytes.len().min(ENCODING_SNIFF_LEN)];
+    if is_binary(head) {
+        return None;
+    }
+    Some(detect_encoding(head).decode(bytes).into_owned())
+}
+
+/// Lowercase hex digits, two per byte.
+pub fn to_hex(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        let _ = write!(out, "{b:02x}");
+    }
+    out
+}
+
+/// `hexdump -C` style view of `bytes`, with offsets starting at
+/// `base_offset`:
+///
+/// ```text
+/// 00000000  89 50 4e 47 0d 0a 1a 0a  00 00 00 0d 49 48 44 52  |.PNG........IHDR|
+/// ```
+pub fn to_hexdump(bytes: &[u8], base_offset: u64) -> String {
+    let mut out = String::with_capacity(bytes.len() * 4 + 16);
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        let _ = write!(out, "{:08x} ", base_offset + i as u64 * 16);
+        for col in 0..16 {
+            if col == 8 {
+                out.push(' ');
+            }
+            match chunk.get(col) {
+                Some(b) => {
+                    let _ = write!(out, " {b:02x}");
+                }
+                None => out.push_str("   "),
+            }
+        }
+        out.push_str("  |");
+        out.extend(chunk.iter().map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        }));
+        out.push_str("|\n");
+    }
+    out
+}
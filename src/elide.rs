@@ -0,0 +1,152 @@
+//! Elided file views for `read_file`: signatures, imports and a focus
+//! region are kept verbatim while function bodies elsewhere are collapsed.
+//!
+//! This is a language-agnostic heuristic on indentation and block openers
+//! (`{` or a trailing `:`), so it works on any file without a parser.
+
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+/// Bodies shorter than this are kept; collapsing them saves little.
+const MIN_ELIDED_LINES: usize = 3;
+
+/// How many lines a multi-line signature may span before its block opener.
+const MAX_SIGNATURE_LINES: usize = 10;
+
+/// Statements that look like a C-style signature (`if (x) {`) but are not.
+const CONTROL_KEYWORDS: &[&str] = &[
+    "if", "else", "for", "while", "switch", "match", "return", "catch", "do", "new", "throw",
+    "case", "try", "elif", "with", "await", "yield", "delete", "sizeof",
+];
+
+/// Lines that start a function or method in common languages, e.g.
+/// `pub async fn foo(`, `def foo(`, `function foo(`, `func (r *T) Foo(`,
+/// `public static int foo(`.
+static FUNCTION_START: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^\s*(?:(?:pub(?:\([^)]*\))?|export|default|async|static|public|private|protected|internal|abstract|final|unsafe|extern|override|virtual|inline|const)\s+)*(?:fn|def|function|func)\b|^\s*(?:[\w:<>\[\],*&]+\s+)+[\w:~]+\s*\([^;]*$",
+    )
+    .expect("valid function start regex")
+});
+
+/// One line of an elided view.
+pub struct ViewLine {
+    /// 1-based line number in the file, or `None` for an elision marker.
+    pub number: Option<u64>,
+    pub text: String,
+}
+
+/// Build an elided view of `lines` (the whole file, line 1 first), keeping
+/// `focus` (1-based, inclusive) verbatim. Returns the view and the collapsed
+/// line ranges.
+pub fn elide(lines: &[String], focus: (u64, u64)) -> (Vec<ViewLine>, Vec<(u64, u64)>) {
+    let in_focus = |idx: usize| {
+        let n = idx as u64 + 1;
+        n >= focus.0 && n <= focus.1
+    };
+
+    let mut view = Vec::with_capacity(lines.len());
+    let mut elided = Vec::new();
+    let mut idx = 0;
+
+    while idx < lines.len() {
+        let body = function_body(lines, idx)
+            .filter(|&(start, end)| end - start + 1 >= MIN_ELIDED_LINES)
+            .filter(|&(start, end)| !(start..=end).any(in_focus));
+
+        let Some((body_start, body_end)) = body else {
+            view.push(ViewLine {
+                number: Some(idx as u64 + 1),
+                text: lines[idx].clone(),
+            });
+            idx += 1;
+            continue;
+        };
+
+        for (i, line) in lines.iter().enumerate().take(body_start).skip(idx) {
+            view.push(ViewLine {
+                number: Some(i as u64 + 1),
+                text: line.clone(),
+            });
+        }
+
+        let range = (body_start as u64 + 1, body_end as u64 + 1);
+        let indent = leading_ws(&lines[body_start]);
+        view.push(ViewLine {
+            number: None,
+            text: format!("{indent}... (lines {}-{} elided)", range.0, range.1),
+        });
+        elided.push(range);
+        idx = body_end + 1;
+    }
+
+    (view, elided)
+}
+
+/// The last file line covered by the first `view_lines` lines of an elided
+/// view with the given collapsed ranges, or `None` for an empty view.
+pub fn view_end(elided: &[(u64, u64)], view_lines: u64) -> Option<u64> {
+    let mut ranges = elided.iter().peekable();
+    let mut line = 0;
+    for _ in 0..view_lines {
+        line = match ranges.next_if(|&&(start, _)| start == line + 1) {
+            Some(&(_, end)) => end,
+            None => line + 1,
+        };
+    }
+    (line > 0).then_some(line)
+}
+
+/// If a function starts at `idx`, return the 0-based inclusive line range
+/// of its body: the lines after the block opener that are indented deeper
+/// than the signature.
+fn function_body(lines: &[String], idx: usize) -> Option<(usize, usize)> {
+    if !FUNCTION_START.is_match(&lines[idx]) {
+        return None;
+    }
+    let first_word = lines[idx].split_whitespace().next().unwrap_or("");
+    if CONTROL_KEYWORDS.contains(&first_word) {
+        return None;
+    }
+    let indent = indent_width(&lines[idx]);
+
+    // Find the line that opens the block, stopping at anything that ends
+    // the signature without one (a declaration or one-line body).
+    let mut opener = None;
+    for (i, line) in lines.iter().enumerate().skip(idx).take(MAX_SIGNATURE_LINES) {
+        let trimmed = line.trim_end();
+        if trimmed.ends_with('{') || trimmed.ends_with(':') {
+            opener = Some(i);
+            break;
+        }
+        if trimmed.ends_with(';') || trimmed.ends_with('}') || (i > idx && line.trim().is_empty()) {
+            return None;
+        }
+    }
+    let body_start = opener? + 1;
+
+    let mut body_end = None;
+    for (i, line) in lines.iter().enumerate().skip(body_start) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if indent_width(line) <= indent {
+            break;
+        }
+        body_end = Some(i);
+    }
+
+    body_end.map(|end| (body_start, end))
+}
+
+fn leading_ws(line: &str) -> &str {
+    &line[..line.len() - line.trim_start().len()]
+}
+
+fn indent_width(line: &str) -> usize {
+    leading_ws(line)
+        .chars()
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum()
+}
@@ -85,7 +85,7 @@ pub enum FsError {
     RootNotDirectory { path: PathBuf },
 
     #[error(
-        "invalid read_file arguments: start_line/max_lines cannot be used with bytes range_type"
+        "invalid read_file arguments: start_line/end_line/max_lines/line_numbers/elide cannot be used with bytes range_type"
     )]
     ReadFileLinesWithBytes,
 
@@ -94,8 +94,117 @@ pub enum FsError {
     )]
     ReadFileBytesWithLines,
 
-    #[error("start_line must be >= 1")]
-    StartLineMustBePositive,
+    #[error("invalid read_file arguments: encoding can only be used with bytes range_type")]
+    ReadFileEncodingWithLines,
+
+    #[error("read_files budget exhausted before reading {path}")]
+    ReadBudgetExhausted { path: String },
+
+    #[error("start_line/end_line must not be 0 (lines are 1-based, negative counts from the end)")]
+    LineNumberZero,
+
+    #[error("invalid read_file arguments: symbol cannot be combined with start_line/end_line")]
+    ReadFileSymbolWithRange,
+
+    #[error(
+        "no parser available for {path} (supported: Rust, TypeScript, JavaScript, Python, Go, C, C++, Java)"
+    )]
+    UnsupportedLanguage { path: PathBuf },
+
+    #[error("{path} is too large to parse ({size} bytes, limit {limit})")]
+    SourceTooLarge {
+        path: PathBuf,
+        size: u64,
+        limit: u64,
+    },
+
+    #[error("symbol {symbol:?} not found in {path}; use file_outline to list symbols")]
+    SymbolNotFound { path: PathBuf, symbol: String },
+
+    #[error(
+        "unknown language {name:?} (expected rust, typescript, tsx, javascript, python, go, c, cpp or java)"
+    )]
+    UnknownLanguage { name: String },
+
+    #[error("pattern {pattern:?} is not valid code in any supported language")]
+    InvalidPattern { pattern: String },
+
+    #[error(
+        "no language server configured for {path}; add one under language_servers in the --config file"
+    )]
+    NoLanguageServer { path: PathBuf },
+
+    #[error("failed to start language server {command}: {source}")]
+    StartLanguageServer {
+        command: String,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("language server {server}: {message}")]
+    LanguageServer { server: String, message: String },
+
+    #[error("language server {server} did not answer {method} within {timeout_ms} ms")]
+    LanguageServerTimeout {
+        server: String,
+        method: String,
+        timeout_ms: u64,
+    },
+
+    #[error("no identifier at line {line}, column {column} of {path}")]
+    NoIdentifierAtPosition {
+        path: PathBuf,
+        line: u64,
+        column: u64,
+    },
+
+    #[error("invalid identifier {name:?}")]
+    InvalidIdentifier { name: String },
+
+    #[error("language server {server} cannot rename the symbol at this position")]
+    RenameRejected { server: String },
+
+    #[error(
+        "run_command is disabled; list allowed commands under run_command.allow in the --config file"
+    )]
+    RunCommandDisabled,
+
+    #[error("run_command cwd is not a directory: {path}")]
+    CommandCwdNotDirectory { path: PathBuf },
+
+    #[error("command {command:?} is not allowed (allowed: {allowed})")]
+    CommandNotAllowed { command: String, allowed: String },
+
+    #[error("failed to run {command:?}: {source}")]
+    RunCommand {
+        command: String,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("diagnostics needs a path or compiler output to parse")]
+    DiagnosticsTargetMissing,
+
+    #[error("invalid {format} output: {source}")]
+    InvalidCompilerOutput {
+        format: String,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("{path} was not written: content does not parse as {language}: {errors}")]
+    InvalidSyntax {
+        path: PathBuf,
+        language: String,
+        errors: String,
+    },
+
+    #[error("line {line} is out of range for {path} ({total_lines} lines)")]
+    LineOutOfRange {
+        path: PathBuf,
+        line: u64,
+        total_lines: u64,
+    },
 
     #[error("failed to open file {path}: {source}")]
     OpenFile {
@@ -125,9 +234,45 @@ pub enum FsError {
         source: io::Error,
     },
 
+    #[error("{error}; these files were already rewritten and could not be restored: {modified}")]
+    RewriteNotRolledBack {
+        #[source]
+        error: Box<FsError>,
+        modified: String,
+    },
+
     #[error("file is not valid UTF-8, binary files are not supported: {path}")]
     FileNotUtf8 { path: PathBuf },
 
+    #[error("failed to read archive {path}: {source}")]
+    ReadArchive {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("archive member not found: {member} in {archive}")]
+    ArchiveMemberNotFound { archive: PathBuf, member: String },
+
+    #[error("content cannot be represented in the file's {encoding} encoding: {path}")]
+    UnencodableContent { path: PathBuf, encoding: String },
+
+    #[error(
+        "{path} is too large to return as media ({size} bytes, limit {limit}); use max_image_dimension or a bytes range"
+    )]
+    MediaTooLarge {
+        path: PathBuf,
+        size: u64,
+        limit: u64,
+    },
+
+    #[error("failed to decode image {path}: {source}")]
+    DecodeImage {
+        path: PathBuf,
+        #[source]
+        source: image::ImageError,
+    },
+
     #[error("failed to get metadata for {path}: {source}")]
     FileMetadata {
         path: PathBuf,
@@ -183,4 +328,32 @@ pub enum FsError {
 
     #[error("move across different git repositories is not allowed: {from} -> {to}")]
     MoveAcrossRepos { from: PathBuf, to: PathBuf },
+
+    #[error("failed to read config file {path}: {source}")]
+    ReadConfig {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("failed to parse config file {path}: {source}")]
+    ParseConfig {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("invalid file type definition {name}:{glob}: {source}")]
+    InvalidFileTypeDef {
+        name: String,
+        glob: String,
+        #[source]
+        source: ignore::Error,
+    },
+
+    #[error("invalid file type filter: {source}")]
+    InvalidFileType {
+        #[source]
+        source: ignore::Error,
+    },
 }
@@ -0,0 +1,220 @@
+//! Formatting of file content before it is written: picks the formatter for
+//! a file from its extension and the project's formatter config files, and
+//! pipes the content through it.
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use crate::config::FormatOnWriteConfig;
+
+const DEFAULT_TIMEOUT_MS: u64 = 10_000;
+/// Stderr lines kept in the message of a failed run.
+const MAX_ERROR_LINES: usize = 10;
+
+const PRETTIER_EXTENSIONS: &[&str] = &[
+    "js", "jsx", "mjs", "cjs", "ts", "tsx", "mts", "cts", "json", "css", "scss", "less", "html",
+    "vue", "md", "yaml", "yml", "graphql",
+];
+const PRETTIER_CONFIGS: &[&str] = &[
+    ".prettierrc",
+    ".prettierrc.json",
+    ".prettierrc.yaml",
+    ".prettierrc.yml",
+    ".prettierrc.json5",
+    ".prettierrc.toml",
+];
+/// Prettier configs that are scripts: prettier would execute project code.
+const PRETTIER_SCRIPT_CONFIGS: &[&str] = &[
+    ".prettierrc.js",
+    ".prettierrc.cjs",
+    ".prettierrc.mjs",
+    ".prettierrc.ts",
+    "prettier.config.js",
+    "prettier.config.cjs",
+    "prettier.config.mjs",
+    "prettier.config.ts",
+];
+const CLANG_FORMAT_EXTENSIONS: &[&str] =
+    &["c", "h", "cc", "cpp", "cxx", "hpp", "hh", "hxx", "proto"];
+const RUFF_CONFIGS: &[&str] = &["ruff.toml", ".ruff.toml"];
+
+/// A formatter command ready to run for one file.
+#[derive(Debug)]
+pub struct Formatter {
+    /// Short name reported to the caller, e.g. `"rustfmt"`.
+    pub name: String,
+    argv: Vec<String>,
+    /// Directory the formatter runs in, so it finds the project's config.
+    cwd: PathBuf,
+}
+
+/// The formatter for `path`, or `None` if the file has none.
+///
+/// Entries in `config.formatters` win. Otherwise rustfmt and gofmt are
+/// used for their languages, while prettier, black/ruff and clang-format
+/// are only used when a config file for them is found between the file
+/// and `top` (the server root or the file's repository root), so projects
+/// that do not use them are not reformatted.
+///
+/// Built-in formatters are always looked up on `PATH`, never inside the
+/// project: files there can be written by the same tools that trigger
+/// formatting, so running them would bypass `run_command`'s allow-list.
+pub fn select(path: &Path, top: &Path, config: &FormatOnWriteConfig) -> Option<Formatter> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    let dir = path.parent()?.to_path_buf();
+    let file = path.to_string_lossy().into_owned();
+    let ancestors: Vec<&Path> = dir
+        .ancestors()
+        .take_while(|ancestor| ancestor.starts_with(top))
+        .collect();
+    let find = |names: &[&str]| {
+        ancestors
+            .iter()
+            .flat_map(|ancestor| names.iter().map(move |name| ancestor.join(name)))
+            .find(|candidate| candidate.is_file())
+    };
+    let formatter = |argv: Vec<String>| {
+        Some(Formatter {
+            name: Path::new(argv.first()?)
+                .file_name()?
+                .to_string_lossy()
+                .into_owned(),
+            argv,
+            // `create_file` formats before creating missing parents.
+            cwd: dir
+                .ancestors()
+                .find(|ancestor| ancestor.is_dir())
+                .unwrap_or(&dir)
+                .to_path_buf(),
+        })
+    };
+
+    if let Some(argv) = config.formatters.get(&extension) {
+        let argv = argv
+            .iter()
+            .map(|arg| arg.replace("{path}", &file))
+            .collect();
+        return formatter(argv);
+    }
+
+    match extension.as_str() {
+        "rs" => {
+            // Workspace members may inherit the edition from a manifest
+            // further up.
+            let edition = ancestors
+                .iter()
+                .filter_map(|ancestor| std::fs::read_to_string(ancestor.join("Cargo.toml")).ok())
+                .find_map(|manifest| cargo_edition(&manifest))
+                .unwrap_or_else(|| "2021".to_string());
+            formatter(vec!["rustfmt".into(), "--edition".into(), edition])
+        }
+        "go" => formatter(vec!["gofmt".into()]),
+        "py" | "pyi" => {
+            let pyproject = find(&["pyproject.toml"])
+                .and_then(|pyproject| std::fs::read_to_string(pyproject).ok())
+                .unwrap_or_default();
+            if find(RUFF_CONFIGS).is_some() || pyproject.contains("[tool.ruff") {
+                let args = ["format", "--stdin-filename", &file, "-"];
+                formatter(command("ruff", args))
+            } else if pyproject.contains("[tool.black") {
+                let args = ["--quiet", "--stdin-filename", &file, "-"];
+                formatter(command("black", args))
+            } else {
+                None
+            }
+        }
+        ext if PRETTIER_EXTENSIONS.contains(&ext) => {
+            let package_json = find(&["package.json"])
+                .and_then(|package| std::fs::read_to_string(package).ok())
+                .unwrap_or_default();
+            if find(PRETTIER_SCRIPT_CONFIGS).is_some()
+                || find(PRETTIER_CONFIGS).is_none() && !package_json.contains("\"prettier\"")
+            {
+                return None;
+            }
+            formatter(command("prettier", ["--stdin-filepath", &file]))
+        }
+        ext if CLANG_FORMAT_EXTENSIONS.contains(&ext) => {
+            find(&[".clang-format", "_clang-format"])?;
+            let assume = format!("--assume-filename={file}");
+            formatter(command("clang-format", [assume.as_str()]))
+        }
+        _ => None,
+    }
+}
+
+impl Formatter {
+    /// Pipe `content` through the formatter. Errors are messages for the
+    /// caller: the formatter is missing, timed out or rejected the input.
+    pub fn run(&self, content: &str, timeout_ms: Option<u64>) -> Result<String, String> {
+        let timeout = Duration::from_millis(timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS));
+        let (program, args) = self.argv.split_first().ok_or("empty formatter command")?;
+        let mut child = Command::new(program)
+            .args(args)
+            .current_dir(&self.cwd)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::NotFound => format!("{program} is not installed"),
+                _ => format!("failed to run {program}: {err}"),
+            })?;
+
+        // Write from another thread: large inputs fill the stdout pipe
+        // before the formatter finishes reading.
+        let mut stdin = child.stdin.take().ok_or("formatter stdin unavailable")?;
+        let input = content.to_string();
+        std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+
+        let pid = child.id();
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || tx.send(child.wait_with_output()));
+        let output = match rx.recv_timeout(timeout) {
+            Ok(output) => output.map_err(|err| format!("failed to run {program}: {err}"))?,
+            Err(_) => {
+                #[cfg(unix)]
+                // SAFETY: `kill` has no memory safety requirements.
+                unsafe {
+                    libc::kill(pid as libc::pid_t, libc::SIGKILL);
+                }
+                return Err(format!(
+                    "{} did not finish within {} ms",
+                    self.name,
+                    timeout.as_millis()
+                ));
+            }
+        };
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let message: Vec<&str> = stderr.lines().take(MAX_ERROR_LINES).collect();
+            return Err(format!(
+                "{} failed ({}): {}",
+                self.name,
+                output.status,
+                message.join("\n").trim_end()
+            ));
+        }
+        String::from_utf8(output.stdout).map_err(|_| format!("{} printed invalid UTF-8", self.name))
+    }
+}
+
+fn command<'a>(program: &'a str, args: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    std::iter::once(program)
+        .chain(args)
+        .map(str::to_string)
+        .collect()
+}
+
+/// `edition = "2021"` from the `[package]` table of a Cargo manifest.
+fn cargo_edition(manifest: &str) -> Option<String> {
+    manifest.lines().find_map(|line| {
+        let value = line.trim().strip_prefix("edition")?.trim_start();
+        let value = value.strip_prefix('=')?.trim();
+        Some(value.trim_matches('"').to_string()).filter(|edition| !edition.is_empty())
+    })
+}
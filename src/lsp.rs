@@ -0,0 +1,717 @@
+//! Bridge to external language servers (rust-analyzer, pyright, gopls, ...)
+//! for `goto_definition`, `find_references`, `hover` and `diagnostics`.
+//!
+//! Servers are configured per language in `ServerConfig::language_servers`,
+//! started lazily over stdio on first use and kept running for the lifetime
+//! of the file server. Documents are opened with their current content
+//! before each request and re-sent whenever the file changes on disk.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, mpsc};
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+use url::Url;
+
+use crate::config::LanguageServerConfig;
+use crate::error::{FsError, Result};
+use crate::syntax::Language;
+
+/// Default time to wait for a language server response.
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// LSP `ContentModified`: the server's view changed while answering.
+const CONTENT_MODIFIED: i64 = -32801;
+/// Attempts for a request the server keeps answering with `ContentModified`,
+/// which rust-analyzer does while it is still indexing.
+const MAX_ATTEMPTS: u32 = 5;
+const RETRY_DELAY: Duration = Duration::from_millis(200);
+/// Time a server gets to answer `shutdown` before it is killed.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Running language servers, keyed by their `language_servers` name.
+pub struct LspClients {
+    root: PathBuf,
+    configs: BTreeMap<String, LanguageServerConfig>,
+    /// One slot per configured server, so starting a server (which waits
+    /// for its `initialize` reply) only blocks requests to that server.
+    clients: HashMap<String, Mutex<Option<Arc<LspClient>>>>,
+}
+
+impl LspClients {
+    pub fn new(root: PathBuf, configs: BTreeMap<String, LanguageServerConfig>) -> Self {
+        let clients = configs
+            .keys()
+            .map(|name| (name.clone(), Mutex::new(None)))
+            .collect();
+        Self {
+            root,
+            configs,
+            clients,
+        }
+    }
+
+    /// The language server responsible for `path`, started on first use and
+    /// restarted if it has exited.
+    pub fn client_for(&self, path: &Path) -> Result<Arc<LspClient>> {
+        let Some((name, config)) = self.config_for(path) else {
+            return Err(FsError::NoLanguageServer {
+                path: path.to_path_buf(),
+            });
+        };
+
+        let mut slot = self.clients[name]
+            .lock()
+            .expect("lsp client slot mutex poisoned");
+        if let Some(client) = slot.as_ref()
+            && client.is_alive()
+        {
+            return Ok(client.clone());
+        }
+        let client = Arc::new(LspClient::start(name, config, &self.root)?);
+        *slot = Some(client.clone());
+        Ok(client)
+    }
+
+    /// A server matches by its explicit `extensions`, or else when its name
+    /// is the language detected from the file extension (`"rust"`, ...).
+    fn config_for(&self, path: &Path) -> Option<(&str, &LanguageServerConfig)> {
+        let ext = path.extension()?.to_str()?;
+        let language = Language::for_path(path);
+        self.configs
+            .iter()
+            .find(|(name, config)| {
+                if config.extensions.is_empty() {
+                    language.is_some() && Language::from_name(name) == language
+                } else {
+                    config
+                        .extensions
+                        .iter()
+                        .any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(ext))
+                }
+            })
+            .map(|(name, config)| (name.as_str(), config))
+    }
+}
+
+/// A zero-based LSP position: line and character offset in the server's
+/// position encoding.
+#[derive(Debug, Clone, Copy)]
+pub struct Position {
+    pub line: u32,
+    pub character: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Range {
+    fn from_value(value: &Value) -> Option<Self> {
+        let position = |value: &Value| {
+            Some(Position {
+                line: value.get("line")?.as_u64()? as u32,
+                character: value.get("character")?.as_u64()? as u32,
+            })
+        };
+        Some(Self {
+            start: position(value.get("start")?)?,
+            end: position(value.get("end")?)?,
+        })
+    }
+}
+
+/// Replacement text for a range.
+pub type TextEdit = (Range, String);
+
+/// Error reply or transport failure of a single request.
+enum Failure {
+    Response { code: i64, message: String },
+    Timeout,
+    Exited,
+    Io(io::Error),
+}
+
+type Reply = std::result::Result<Value, Failure>;
+
+/// State shared with the thread reading the server's stdout.
+#[derive(Default)]
+struct Shared {
+    pending: Mutex<HashMap<i64, mpsc::Sender<Reply>>>,
+    diagnostics: Mutex<HashMap<PathBuf, Published>>,
+    published: Condvar,
+    alive: AtomicBool,
+}
+
+/// Latest `textDocument/publishDiagnostics` for a file, with a counter of
+/// how many times it has been published.
+#[derive(Default)]
+struct Published {
+    generation: u64,
+    diagnostics: Vec<Value>,
+}
+
+/// What was last sent to the server for an open document.
+struct OpenDocument {
+    version: i32,
+    /// Hash of the text last sent. Compared instead of size and mtime so
+    /// same-size edits within the mtime granularity are not missed.
+    hash: u64,
+}
+
+/// A running language server process.
+pub struct LspClient {
+    name: String,
+    child: Mutex<Child>,
+    writer: Arc<Mutex<ChildStdin>>,
+    shared: Arc<Shared>,
+    next_id: AtomicI64,
+    timeout: Duration,
+    /// Whether the server agreed to UTF-8 (byte) positions instead of the
+    /// default UTF-16 code units.
+    utf8_positions: AtomicBool,
+    documents: Mutex<HashMap<PathBuf, OpenDocument>>,
+}
+
+impl LspClient {
+    fn start(name: &str, config: &LanguageServerConfig, root: &Path) -> Result<Self> {
+        let mut child = Command::new(&config.command)
+            .args(&config.args)
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|source| FsError::StartLanguageServer {
+                command: config.command.clone(),
+                source,
+            })?;
+
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            let _ = child.kill();
+            return Err(FsError::LanguageServer {
+                server: name.to_string(),
+                message: "stdio not available".to_string(),
+            });
+        };
+
+        let shared = Arc::new(Shared {
+            alive: AtomicBool::new(true),
+            ..Shared::default()
+        });
+        let writer = Arc::new(Mutex::new(stdin));
+        {
+            let shared = shared.clone();
+            let writer = writer.clone();
+            let name = name.to_string();
+            std::thread::spawn(move || read_loop(&name, stdout, &shared, &writer));
+        }
+
+        let client = Self {
+            name: name.to_string(),
+            child: Mutex::new(child),
+            writer,
+            shared,
+            next_id: AtomicI64::new(1),
+            timeout: config
+                .request_timeout_ms
+                .map_or(DEFAULT_REQUEST_TIMEOUT, Duration::from_millis),
+            utf8_positions: AtomicBool::new(false),
+            documents: Mutex::new(HashMap::new()),
+        };
+
+        let root_uri = Url::from_directory_path(root)
+            .map(String::from)
+            .unwrap_or_default();
+        let root_name = root
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let result = client.request(
+            "initialize",
+            json!({
+                "processId": std::process::id(),
+                "rootUri": root_uri,
+                "workspaceFolders": [{ "uri": root_uri, "name": root_name }],
+                "initializationOptions": config.initialization_options,
+                "capabilities": {
+                    "general": { "positionEncodings": ["utf-8", "utf-16"] },
+                    "textDocument": {
+                        "synchronization": { "didSave": false },
+                        "definition": { "linkSupport": true },
+                        "references": {},
+                        "hover": { "contentFormat": ["markdown", "plaintext"] },
+                        "publishDiagnostics": {}
+                    },
+                    "workspace": { "workspaceFolders": true, "configuration": true }
+                }
+            }),
+        )?;
+        let encoding = result
+            .pointer("/capabilities/positionEncoding")
+            .and_then(Value::as_str);
+        client
+            .utf8_positions
+            .store(encoding == Some("utf-8"), Ordering::Relaxed);
+        client.notify("initialized", json!({}))?;
+
+        Ok(client)
+    }
+
+    /// Name of the `language_servers` entry this server was started from.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.shared.alive.load(Ordering::Relaxed)
+    }
+
+    /// Send a request and wait for its result, retrying while the server
+    /// reports `ContentModified`.
+    pub fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let mut attempt = 1;
+        loop {
+            match self.request_once(method, params.clone(), self.timeout) {
+                Ok(value) => return Ok(value),
+                Err(Failure::Response { code, .. })
+                    if code == CONTENT_MODIFIED && attempt < MAX_ATTEMPTS =>
+                {
+                    attempt += 1;
+                    std::thread::sleep(RETRY_DELAY);
+                }
+                Err(failure) => return Err(self.error(method, failure)),
+            }
+        }
+    }
+
+    fn request_once(&self, method: &str, params: Value, timeout: Duration) -> Reply {
+        if !self.is_alive() {
+            return Err(Failure::Exited);
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = mpsc::channel();
+        self.pending().insert(id, tx);
+
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        if let Err(err) = write_message(&self.writer, &message) {
+            self.pending().remove(&id);
+            return Err(Failure::Io(err));
+        }
+
+        match rx.recv_timeout(timeout) {
+            Ok(reply) => reply,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                self.pending().remove(&id);
+                let _ = self.notify("$/cancelRequest", json!({ "id": id }));
+                Err(Failure::Timeout)
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err(Failure::Exited),
+        }
+    }
+
+    pub fn notify(&self, method: &str, params: Value) -> Result<()> {
+        let message = json!({ "jsonrpc": "2.0", "method": method, "params": params });
+        write_message(&self.writer, &message).map_err(|err| self.error(method, Failure::Io(err)))
+    }
+
+    /// Make the server's copy of `path` match `text`: open it on first use
+    /// and send the full content again when it differs from what was sent.
+    /// Returns whether anything was sent.
+    pub fn sync_document(&self, path: &Path, text: &str) -> Result<bool> {
+        let uri = file_uri(path);
+        let mut hasher = DefaultHasher::new();
+        text.hash(&mut hasher);
+        let hash = hasher.finish();
+        let mut documents = self.documents.lock().expect("lsp documents mutex poisoned");
+        match documents.get_mut(path) {
+            Some(doc) if doc.hash == hash => Ok(false),
+            Some(doc) => {
+                doc.version += 1;
+                doc.hash = hash;
+                self.notify(
+                    "textDocument/didChange",
+                    json!({
+                        "textDocument": { "uri": uri, "version": doc.version },
+                        "contentChanges": [{ "text": text }]
+                    }),
+                )?;
+                Ok(true)
+            }
+            None => {
+                self.notify(
+                    "textDocument/didOpen",
+                    json!({
+                        "textDocument": {
+                            "uri": uri,
+                            "languageId": language_id(path),
+                            "version": 1,
+                            "text": text
+                        }
+                    }),
+                )?;
+                documents.insert(path.to_path_buf(), OpenDocument { version: 1, hash });
+                Ok(true)
+            }
+        }
+    }
+
+    /// How many times diagnostics for `path` have been published so far.
+    pub fn diagnostics_generation(&self, path: &Path) -> u64 {
+        self.lock_diagnostics()
+            .get(path)
+            .map_or(0, |published| published.generation)
+    }
+
+    /// Diagnostics for `path`, waiting up to `wait` for a publication newer
+    /// than `since`. Returns whatever was last published if none arrives.
+    pub fn diagnostics(&self, path: &Path, since: u64, wait: Duration) -> Vec<Value> {
+        let deadline = Instant::now() + wait;
+        let mut diagnostics = self.lock_diagnostics();
+        loop {
+            let generation = diagnostics.get(path).map_or(0, |p| p.generation);
+            let now = Instant::now();
+            if generation > since || now >= deadline || !self.is_alive() {
+                break;
+            }
+            diagnostics = self
+                .shared
+                .published
+                .wait_timeout(diagnostics, deadline - now)
+                .expect("lsp diagnostics mutex poisoned")
+                .0;
+        }
+        diagnostics
+            .get(path)
+            .map(|p| p.diagnostics.clone())
+            .unwrap_or_default()
+    }
+
+    /// LSP character offset of byte offset `byte` in `line`.
+    pub fn to_character(&self, line: &str, byte: usize) -> u32 {
+        let mut byte = byte.min(line.len());
+        while !line.is_char_boundary(byte) {
+            byte -= 1;
+        }
+        if self.utf8_positions.load(Ordering::Relaxed) {
+            byte as u32
+        } else {
+            line[..byte].encode_utf16().count() as u32
+        }
+    }
+
+    /// Byte offset in `line` of LSP character offset `character`.
+    pub fn to_byte(&self, line: &str, character: u32) -> usize {
+        if self.utf8_positions.load(Ordering::Relaxed) {
+            let mut byte = (character as usize).min(line.len());
+            while !line.is_char_boundary(byte) {
+                byte -= 1;
+            }
+            return byte;
+        }
+        let mut units = 0;
+        for (byte, c) in line.char_indices() {
+            if units >= character as usize {
+                return byte;
+            }
+            units += c.len_utf16();
+        }
+        line.len()
+    }
+
+    fn error(&self, method: &str, failure: Failure) -> FsError {
+        let server = self.name.clone();
+        match failure {
+            Failure::Timeout => FsError::LanguageServerTimeout {
+                server,
+                method: method.to_string(),
+                timeout_ms: self.timeout.as_millis() as u64,
+            },
+            Failure::Response { code, message } => FsError::LanguageServer {
+                server,
+                message: format!("{method} failed ({code}): {message}"),
+            },
+            Failure::Exited => FsError::LanguageServer {
+                server,
+                message: "server exited".to_string(),
+            },
+            Failure::Io(err) => FsError::LanguageServer {
+                server,
+                message: format!("failed to send {method}: {err}"),
+            },
+        }
+    }
+
+    fn pending(&self) -> MutexGuard<'_, HashMap<i64, mpsc::Sender<Reply>>> {
+        self.shared
+            .pending
+            .lock()
+            .expect("lsp pending mutex poisoned")
+    }
+
+    fn lock_diagnostics(&self) -> MutexGuard<'_, HashMap<PathBuf, Published>> {
+        self.shared
+            .diagnostics
+            .lock()
+            .expect("lsp diagnostics mutex poisoned")
+    }
+}
+
+impl Drop for LspClient {
+    fn drop(&mut self) {
+        // The protocol requires `shutdown` before `exit`; servers may
+        // otherwise exit with an error or skip cleanup.
+        let _ = self.request_once("shutdown", Value::Null, SHUTDOWN_TIMEOUT);
+        let _ = self.notify("exit", Value::Null);
+        if let Ok(child) = self.child.get_mut() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+/// `file://` URI of `path`.
+pub fn file_uri(path: &Path) -> String {
+    Url::from_file_path(path)
+        .map(String::from)
+        .unwrap_or_else(|()| format!("file://{}", path.display()))
+}
+
+/// Local path of a `file://` URI.
+pub fn uri_path(uri: &str) -> Option<PathBuf> {
+    Url::parse(uri).ok()?.to_file_path().ok()
+}
+
+/// Flatten a `textDocument/definition` or `references` result (`Location`,
+/// `Location[]`, `LocationLink[]` or `null`) into paths and ranges.
+pub fn locations(value: &Value) -> Vec<(PathBuf, Range)> {
+    let items = match value {
+        Value::Array(items) => items.as_slice(),
+        Value::Null => &[],
+        single => std::slice::from_ref(single),
+    };
+    items
+        .iter()
+        .filter_map(|item| {
+            // `LocationLink` points at the whole target; prefer the name.
+            let (uri, range) = match item.get("targetUri") {
+                Some(uri) => (
+                    uri,
+                    item.get("targetSelectionRange")
+                        .or_else(|| item.get("targetRange"))?,
+                ),
+                None => (item.get("uri")?, item.get("range")?),
+            };
+            Some((uri_path(uri.as_str()?)?, Range::from_value(range)?))
+        })
+        .collect()
+}
+
+/// Text edits per file from a `WorkspaceEdit` (`textDocument/rename`),
+/// reading both `changes` and `documentChanges`. Returns `None` if the edit
+/// creates, renames or deletes files, which is not supported.
+pub fn workspace_edit(value: &Value) -> Option<Vec<(PathBuf, Vec<TextEdit>)>> {
+    let text_edits = |edits: &Value| -> Option<Vec<TextEdit>> {
+        edits
+            .as_array()?
+            .iter()
+            .map(|edit| Some((range_of(edit)?, edit.get("newText")?.as_str()?.to_string())))
+            .collect()
+    };
+
+    let mut files = Vec::new();
+    if let Some(changes) = value.get("documentChanges").and_then(Value::as_array) {
+        for change in changes {
+            // File operations carry a `kind` (`create`, `rename`, `delete`).
+            if change.get("kind").is_some() {
+                return None;
+            }
+            let uri = change.pointer("/textDocument/uri")?.as_str()?;
+            files.push((uri_path(uri)?, text_edits(change.get("edits")?)?));
+        }
+    } else if let Some(changes) = value.get("changes").and_then(Value::as_object) {
+        for (uri, edits) in changes {
+            files.push((uri_path(uri)?, text_edits(edits)?));
+        }
+    }
+    Some(files)
+}
+
+/// Range of a diagnostic or other object with a `range` field.
+pub fn range_of(value: &Value) -> Option<Range> {
+    Range::from_value(value.get("range")?)
+}
+
+/// Plain text of a `textDocument/hover` result, whose `contents` may be a
+/// `MarkupContent`, a `MarkedString` or an array of them.
+pub fn hover_text(value: &Value) -> Option<String> {
+    fn text(contents: &Value) -> Option<String> {
+        match contents {
+            Value::String(s) => Some(s.clone()),
+            Value::Array(items) => {
+                let parts: Vec<String> = items.iter().filter_map(text).collect();
+                (!parts.is_empty()).then(|| parts.join("\n\n"))
+            }
+            Value::Object(map) => {
+                let value = map.get("value")?.as_str()?;
+                match map.get("language").and_then(Value::as_str) {
+                    Some(language) => Some(format!("```{language}\n{value}\n```")),
+                    None => Some(value.to_string()),
+                }
+            }
+            _ => None,
+        }
+    }
+    text(value.get("contents")?).filter(|s| !s.trim().is_empty())
+}
+
+/// LSP `languageId` for a document.
+fn language_id(path: &Path) -> String {
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    match (Language::for_path(path), ext.as_str()) {
+        (Some(Language::Tsx), _) => "typescriptreact".to_string(),
+        (Some(Language::JavaScript), "jsx") => "javascriptreact".to_string(),
+        (Some(language), _) => language.name().to_string(),
+        (None, _) => ext,
+    }
+}
+
+/// Read messages from the server until it exits, routing responses to the
+/// waiting requests, recording published diagnostics and answering the
+/// server's own requests.
+fn read_loop(name: &str, stdout: ChildStdout, shared: &Shared, writer: &Mutex<ChildStdin>) {
+    let mut reader = BufReader::new(stdout);
+    loop {
+        let message = match read_message(&mut reader) {
+            Ok(Some(message)) => message,
+            Ok(None) => break,
+            Err(err) => {
+                eprintln!("lsp {name}: stop reading: {err}");
+                break;
+            }
+        };
+
+        let method = message.get("method").and_then(Value::as_str);
+        let id = message.get("id");
+        match (method, id) {
+            (None, Some(id)) => {
+                let Some(id) = id.as_i64() else { continue };
+                let Some(tx) = shared
+                    .pending
+                    .lock()
+                    .expect("lsp pending mutex poisoned")
+                    .remove(&id)
+                else {
+                    continue;
+                };
+                let reply = match message.get("error") {
+                    Some(error) => Err(Failure::Response {
+                        code: error.get("code").and_then(Value::as_i64).unwrap_or(0),
+                        message: error
+                            .get("message")
+                            .and_then(Value::as_str)
+                            .unwrap_or_default()
+                            .to_string(),
+                    }),
+                    None => Ok(message.get("result").cloned().unwrap_or(Value::Null)),
+                };
+                let _ = tx.send(reply);
+            }
+            (Some(method), Some(id)) => {
+                // Requests from the server (configuration, progress tokens,
+                // capability registration) get empty but successful replies.
+                let result = match method {
+                    "workspace/configuration" => {
+                        let items = message
+                            .pointer("/params/items")
+                            .and_then(Value::as_array)
+                            .map_or(0, Vec::len);
+                        Value::Array(vec![Value::Null; items])
+                    }
+                    _ => Value::Null,
+                };
+                let reply = json!({ "jsonrpc": "2.0", "id": id, "result": result });
+                if let Err(err) = write_message(writer, &reply) {
+                    eprintln!("lsp {name}: failed to answer {method}: {err}");
+                }
+            }
+            (Some("textDocument/publishDiagnostics"), None) => {
+                let Some(params) = message.get("params") else {
+                    continue;
+                };
+                let Some(path) = params.get("uri").and_then(Value::as_str).and_then(uri_path)
+                else {
+                    continue;
+                };
+                let diagnostics = params
+                    .get("diagnostics")
+                    .and_then(Value::as_array)
+                    .cloned()
+                    .unwrap_or_default();
+                let mut published = shared
+                    .diagnostics
+                    .lock()
+                    .expect("lsp diagnostics mutex poisoned");
+                let entry = published.entry(path).or_default();
+                entry.generation += 1;
+                entry.diagnostics = diagnostics;
+                shared.published.notify_all();
+            }
+            _ => {}
+        }
+    }
+
+    shared.alive.store(false, Ordering::Relaxed);
+    // Dropping the senders fails every request still waiting for a reply.
+    shared
+        .pending
+        .lock()
+        .expect("lsp pending mutex poisoned")
+        .clear();
+    shared.published.notify_all();
+}
+
+/// Read one `Content-Length` framed JSON-RPC message; `None` at end of stream.
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.trim().eq_ignore_ascii_case("content-length")
+        {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let mut body = vec![0; length.unwrap_or_default()];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn write_message(writer: &Mutex<ChildStdin>, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    let mut writer = writer.lock().expect("lsp writer mutex poisoned");
+    write!(writer, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    writer.flush()
+}
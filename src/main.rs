@@ -1,20 +1,65 @@
+mod archive;
 mod backend;
+mod command;
+mod compiler_output;
+mod config;
+mod content;
+mod elide;
 mod error;
+mod format;
+mod lsp;
 mod mcp_service;
+mod outline;
+mod rank;
+mod stats;
+mod structural;
+mod symbol_index;
+mod syntax;
+mod tokens;
 mod types;
+mod validate;
 
 use std::{env, error::Error, path::PathBuf};
 
 use backend::LocalGitAwareFs;
+use config::ServerConfig;
 use mcp_service::FileServer;
 use rmcp::{ServiceExt, transport::stdio};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let repo_root = env::args().nth(1).unwrap_or_else(|| ".".into());
-    let root = PathBuf::from(repo_root);
+    // Usage: complement_mcp [ROOT] [--config PATH]
+    let mut repo_root = None;
+    let mut config_path = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            let Some(path) = args.next() else {
+                eprintln!("complement_mcp: --config needs a path");
+                return Err("--config needs a path".into());
+            };
+            config_path = Some(PathBuf::from(path));
+        } else if arg.starts_with('-') {
+            eprintln!("complement_mcp: unknown option {arg}");
+            return Err(format!("unknown option {arg}").into());
+        } else if repo_root.is_none() {
+            repo_root = Some(arg);
+        } else {
+            eprintln!("complement_mcp: unexpected argument {arg}");
+            return Err(format!("unexpected argument {arg}").into());
+        }
+    }
+    let root = PathBuf::from(repo_root.unwrap_or_else(|| ".".into()));
 
-    let backend = LocalGitAwareFs::new(root).map_err(|e| {
+    let config = match config_path {
+        Some(path) => ServerConfig::load(&path).map_err(|e| {
+            eprintln!("complement_mcp: failed to load config: {e}");
+            e
+        })?,
+        None => ServerConfig::default(),
+    };
+
+    let backend = LocalGitAwareFs::new(root, config).map_err(|e| {
         eprintln!("complement_mcp: failed to initialize file backend: {e}");
         e
     })?;
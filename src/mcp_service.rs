@@ -1,18 +1,31 @@
 use std::sync::Arc;
 
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
 use rmcp::{
-    ErrorData as McpError, ServerHandler,
+    ErrorData as McpError, RoleServer, ServerHandler,
     handler::server::{router::tool::ToolRouter, wrapper::Parameters},
     model::{
-        CallToolResult, Content, Implementation, ProtocolVersion, ServerCapabilities, ServerInfo,
+        CallToolResult, Content, Implementation, ProgressNotificationParam, ProgressToken,
+        ProtocolVersion, ResourceContents, ServerCapabilities, ServerInfo,
     },
+    service::RequestContext,
     tool, tool_handler, tool_router,
 };
+use tokio::sync::mpsc;
 
-use crate::backend::LocalGitAwareFs;
+use crate::backend::{LocalGitAwareFs, WalkControl, WalkProgress};
+use crate::content;
+use crate::error::FsError;
 use crate::types::{
-    CopyPathArgs, CreateFileArgs, DeletePathArgs, FindFilesArgs, ListFilesArgs, MovePathArgs,
-    OverwriteFileArgs, PathInfoArgs, ReadFileArgs, SearchTextArgs, StatArgs,
+    CopyPathArgs, CreateFileArgs, DeletePathArgs, DiagnosticsArgs, FileOutlineArgs, FindFilesArgs,
+    FindReferencesArgs, ListFileTypesArgs, ListFilesArgs, LspPositionArgs, MovePathArgs,
+    OverwriteFileArgs, PathInfoArgs, ReadFileArgs, ReadFilesArgs, RenameSymbolArgs,
+    ReplaceTextArgs, RepoStatsArgs, RunCommandArgs, SearchStructuralArgs, SearchSymbolsArgs,
+    SearchTextArgs, StatArgs,
 };
 
 #[derive(Clone)]
@@ -26,6 +39,99 @@ impl FileServer {
         let full = format!("{code}: {}", message.into());
         McpError::internal_error(full, None)
     }
+
+    /// Run a blocking backend call (such as a language server request) on
+    /// the blocking thread pool.
+    async fn run_blocking<T, F>(code: &str, call: F) -> Result<T, McpError>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T, FsError> + Send + 'static,
+    {
+        tokio::task::spawn_blocking(call)
+            .await
+            .map_err(|e| Self::internal_error("task_failed", e.to_string()))?
+            .map_err(|e| Self::internal_error(code, e.to_string()))
+    }
+
+    /// Run a blocking backend walk on the blocking thread pool, stopping it
+    /// when the MCP request is cancelled or `timeout_ms` elapses, and
+    /// forwarding its progress as MCP progress notifications when the client
+    /// supplied a progress token.
+    async fn run_walk<T, F>(
+        ctx: RequestContext<RoleServer>,
+        code: &str,
+        timeout_ms: Option<u64>,
+        walk: F,
+    ) -> Result<T, McpError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&WalkControl) -> Result<T, FsError> + Send + 'static,
+    {
+        let progress_token = ctx.meta.get_progress_token();
+        let (tx, mut rx) = mpsc::unbounded_channel::<WalkProgress>();
+
+        let mut control = WalkControl::new(timeout_ms.map(Duration::from_millis));
+        if progress_token.is_some() {
+            control = control.with_progress(move |progress| {
+                let _ = tx.send(progress);
+            });
+        }
+        let cancel_flag = control.cancel_flag();
+
+        let task = tokio::task::spawn_blocking(move || walk(&control));
+        tokio::pin!(task);
+
+        let mut cancel_seen = false;
+        let mut progress_open = true;
+        loop {
+            tokio::select! {
+                joined = &mut task => {
+                    let result = joined
+                        .map_err(|e| Self::internal_error("task_failed", e.to_string()))?;
+                    // Deliver progress sent just before the task finished
+                    // (e.g. the last output of `run_command`).
+                    if let Some(token) = &progress_token {
+                        while let Ok(progress) = rx.try_recv() {
+                            Self::notify_progress(&ctx, token, progress).await;
+                        }
+                    }
+                    return result.map_err(|e| Self::internal_error(code, e.to_string()));
+                }
+                _ = ctx.ct.cancelled(), if !cancel_seen => {
+                    cancel_seen = true;
+                    cancel_flag.store(true, Ordering::Relaxed);
+                }
+                progress = rx.recv(), if progress_open => {
+                    let (Some(progress), Some(token)) = (progress, &progress_token) else {
+                        progress_open = false;
+                        continue;
+                    };
+                    Self::notify_progress(&ctx, token, progress).await;
+                }
+            }
+        }
+    }
+
+    async fn notify_progress(
+        ctx: &RequestContext<RoleServer>,
+        token: &ProgressToken,
+        progress: WalkProgress,
+    ) {
+        let _ = ctx
+            .peer
+            .notify_progress(ProgressNotificationParam {
+                progress_token: token.clone(),
+                progress: progress.entries_scanned as f64,
+                total: None,
+                message: Some(progress.message.unwrap_or_else(|| {
+                    format!(
+                        "{} entries scanned, {} matches so far",
+                        progress.entries_scanned, progress.matches
+                    )
+                })),
+            })
+            .await;
+    }
 }
 
 #[tool_router]
@@ -41,11 +147,117 @@ impl FileServer {
     pub async fn search_text(
         &self,
         Parameters(args): Parameters<SearchTextArgs>,
+        ctx: RequestContext<RoleServer>,
     ) -> Result<CallToolResult, McpError> {
-        let result = self
-            .backend
-            .search_text(args)
-            .map_err(|e| Self::internal_error("search_text_failed", e.to_string()))?;
+        let backend = self.backend.clone();
+        let timeout_ms = args.timeout_ms;
+        let result = Self::run_walk(ctx, "search_text_failed", timeout_ms, move |control| {
+            backend.search_text(args, control)
+        })
+        .await?;
+
+        let json = serde_json::to_string(&result)
+            .map_err(|e| Self::internal_error("serialize_failed", e.to_string()))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    #[tool(
+        description = "Find symbol definitions (functions, types, traits, constants, ...) by name or fuzzy query across the repository (gitignore aware)"
+    )]
+    pub async fn search_symbols(
+        &self,
+        Parameters(args): Parameters<SearchSymbolsArgs>,
+        ctx: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let backend = self.backend.clone();
+        let timeout_ms = args.timeout_ms;
+        let result = Self::run_walk(ctx, "search_symbols_failed", timeout_ms, move |control| {
+            backend.search_symbols(args, control)
+        })
+        .await?;
+
+        let json = serde_json::to_string(&result)
+            .map_err(|e| Self::internal_error("serialize_failed", e.to_string()))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    #[tool(
+        description = "Search code by syntax pattern with metavariables (e.g. `$X.unwrap()`, `foo($$$ARGS)`) across the repository, optionally rewriting matches from a template (gitignore aware)"
+    )]
+    pub async fn search_structural(
+        &self,
+        Parameters(args): Parameters<SearchStructuralArgs>,
+        ctx: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let backend = self.backend.clone();
+        let timeout_ms = args.timeout_ms;
+        let result = Self::run_walk(
+            ctx,
+            "search_structural_failed",
+            timeout_ms,
+            move |control| backend.search_structural(args, control),
+        )
+        .await?;
+
+        let json = serde_json::to_string(&result)
+            .map_err(|e| Self::internal_error("serialize_failed", e.to_string()))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    #[tool(description = "Search and replace text in repository (gitignore aware)")]
+    pub async fn replace_text(
+        &self,
+        Parameters(args): Parameters<ReplaceTextArgs>,
+        ctx: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let backend = self.backend.clone();
+        let timeout_ms = args.timeout_ms;
+        let result = Self::run_walk(ctx, "replace_text_failed", timeout_ms, move |control| {
+            backend.replace_text(args, control)
+        })
+        .await?;
+
+        let json = serde_json::to_string(&result)
+            .map_err(|e| Self::internal_error("serialize_failed", e.to_string()))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    #[tool(
+        description = "Rename a symbol across the repository with the language server configured for the file (textDocument/rename), with diff preview or atomic apply; fails without a language server"
+    )]
+    pub async fn rename_symbol(
+        &self,
+        Parameters(args): Parameters<RenameSymbolArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let backend = self.backend.clone();
+        let result =
+            Self::run_blocking("rename_symbol_failed", move || backend.rename_symbol(args)).await?;
+
+        let json = serde_json::to_string(&result)
+            .map_err(|e| Self::internal_error("serialize_failed", e.to_string()))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    #[tool(
+        description = "Run an allow-listed build or test command (e.g. `cargo test`) in the repository root with a timeout and capped output; output is streamed as progress notifications"
+    )]
+    pub async fn run_command(
+        &self,
+        Parameters(args): Parameters<RunCommandArgs>,
+        ctx: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let backend = self.backend.clone();
+        // The command enforces its own timeout so it can return the output
+        // collected until then.
+        let result = Self::run_walk(ctx, "run_command_failed", None, move |control| {
+            backend.run_command(args, control)
+        })
+        .await?;
 
         let json = serde_json::to_string(&result)
             .map_err(|e| Self::internal_error("serialize_failed", e.to_string()))?;
@@ -69,11 +281,36 @@ impl FileServer {
         Ok(CallToolResult::success(vec![Content::text(json)]))
     }
 
-    #[tool(description = "Read a file or a range from it")]
+    #[tool(
+        description = "Read a file or a range from it; images and PDFs are returned as image / embedded resource content"
+    )]
     pub async fn read_file(
         &self,
         Parameters(args): Parameters<ReadFileArgs>,
     ) -> Result<CallToolResult, McpError> {
+        let media = self
+            .backend
+            .read_media(&args)
+            .map_err(|e| Self::internal_error("read_file_failed", e.to_string()))?;
+
+        if let Some(media) = media {
+            let json = serde_json::to_string(&media.info)
+                .map_err(|e| Self::internal_error("serialize_failed", e.to_string()))?;
+            let data = BASE64_STANDARD.encode(&media.data);
+            let block = if media.info.mime_type == content::PDF_MIME {
+                Content::resource(ResourceContents::BlobResourceContents {
+                    uri: media.uri,
+                    mime_type: Some(media.info.mime_type),
+                    blob: data,
+                    meta: None,
+                })
+            } else {
+                Content::image(data, media.info.mime_type)
+            };
+
+            return Ok(CallToolResult::success(vec![Content::text(json), block]));
+        }
+
         let result = self
             .backend
             .read_file(args)
@@ -85,15 +322,17 @@ impl FileServer {
         Ok(CallToolResult::success(vec![Content::text(json)]))
     }
 
-    #[tool(description = "Create or overwrite a file with optional content")]
-    pub async fn create_file(
+    #[tool(
+        description = "Read several files or ranges in one call within a total byte/line budget"
+    )]
+    pub async fn read_files(
         &self,
-        Parameters(args): Parameters<CreateFileArgs>,
+        Parameters(args): Parameters<ReadFilesArgs>,
     ) -> Result<CallToolResult, McpError> {
         let result = self
             .backend
-            .create_file(args)
-            .map_err(|e| Self::internal_error("create_file_failed", e.to_string()))?;
+            .read_files(args)
+            .map_err(|e| Self::internal_error("read_files_failed", e.to_string()))?;
 
         let json = serde_json::to_string(&result)
             .map_err(|e| Self::internal_error("serialize_failed", e.to_string()))?;
@@ -101,15 +340,120 @@ impl FileServer {
         Ok(CallToolResult::success(vec![Content::text(json)]))
     }
 
-    #[tool(description = "Overwrite an existing file's entire content")]
-    pub async fn overwrite_file(
+    #[tool(
+        description = "List the symbols (functions, types, methods, ...) declared in a source file with their line ranges and signatures"
+    )]
+    pub async fn file_outline(
         &self,
-        Parameters(args): Parameters<OverwriteFileArgs>,
+        Parameters(args): Parameters<FileOutlineArgs>,
     ) -> Result<CallToolResult, McpError> {
         let result = self
             .backend
-            .overwrite_file(args)
-            .map_err(|e| Self::internal_error("overwrite_file_failed", e.to_string()))?;
+            .file_outline(args)
+            .map_err(|e| Self::internal_error("file_outline_failed", e.to_string()))?;
+
+        let json = serde_json::to_string(&result)
+            .map_err(|e| Self::internal_error("serialize_failed", e.to_string()))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    #[tool(
+        description = "Go to the definition of the symbol at a file position using the configured language server"
+    )]
+    pub async fn goto_definition(
+        &self,
+        Parameters(args): Parameters<LspPositionArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let backend = self.backend.clone();
+        let result = Self::run_blocking("goto_definition_failed", move || {
+            backend.goto_definition(args)
+        })
+        .await?;
+
+        let json = serde_json::to_string(&result)
+            .map_err(|e| Self::internal_error("serialize_failed", e.to_string()))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    #[tool(
+        description = "Find references to the symbol at a file position using the configured language server"
+    )]
+    pub async fn find_references(
+        &self,
+        Parameters(args): Parameters<FindReferencesArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let backend = self.backend.clone();
+        let result = Self::run_blocking("find_references_failed", move || {
+            backend.find_references(args)
+        })
+        .await?;
+
+        let json = serde_json::to_string(&result)
+            .map_err(|e| Self::internal_error("serialize_failed", e.to_string()))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    #[tool(
+        description = "Show the type and documentation of the symbol at a file position using the configured language server"
+    )]
+    pub async fn hover(
+        &self,
+        Parameters(args): Parameters<LspPositionArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let backend = self.backend.clone();
+        let result = Self::run_blocking("hover_failed", move || backend.hover(args)).await?;
+
+        let json = serde_json::to_string(&result)
+            .map_err(|e| Self::internal_error("serialize_failed", e.to_string()))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    #[tool(
+        description = "Get errors and warnings for a file from the configured language server, or parse them from compiler output (cargo JSON, tsc, eslint JSON, gcc-style)"
+    )]
+    pub async fn diagnostics(
+        &self,
+        Parameters(args): Parameters<DiagnosticsArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let backend = self.backend.clone();
+        let result =
+            Self::run_blocking("diagnostics_failed", move || backend.diagnostics(args)).await?;
+
+        let json = serde_json::to_string(&result)
+            .map_err(|e| Self::internal_error("serialize_failed", e.to_string()))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    #[tool(description = "Create or overwrite a file with optional content, optionally formatted")]
+    pub async fn create_file(
+        &self,
+        Parameters(args): Parameters<CreateFileArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let backend = self.backend.clone();
+        let result =
+            Self::run_blocking("create_file_failed", move || backend.create_file(args)).await?;
+
+        let json = serde_json::to_string(&result)
+            .map_err(|e| Self::internal_error("serialize_failed", e.to_string()))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    #[tool(description = "Overwrite an existing file's entire content, optionally formatted")]
+    pub async fn overwrite_file(
+        &self,
+        Parameters(args): Parameters<OverwriteFileArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let backend = self.backend.clone();
+        let result = Self::run_blocking("overwrite_file_failed", move || {
+            backend.overwrite_file(args)
+        })
+        .await?;
 
         let json = serde_json::to_string(&result)
             .map_err(|e| Self::internal_error("serialize_failed", e.to_string()))?;
@@ -121,11 +465,14 @@ impl FileServer {
     pub async fn list_files(
         &self,
         Parameters(args): Parameters<ListFilesArgs>,
+        ctx: RequestContext<RoleServer>,
     ) -> Result<CallToolResult, McpError> {
-        let result = self
-            .backend
-            .list_files(args)
-            .map_err(|e| Self::internal_error("list_files_failed", e.to_string()))?;
+        let backend = self.backend.clone();
+        let timeout_ms = args.timeout_ms;
+        let result = Self::run_walk(ctx, "list_files_failed", timeout_ms, move |control| {
+            backend.list_files(args, control)
+        })
+        .await?;
 
         let json = serde_json::to_string(&result)
             .map_err(|e| Self::internal_error("serialize_failed", e.to_string()))?;
@@ -137,11 +484,51 @@ impl FileServer {
     pub async fn find_files(
         &self,
         Parameters(args): Parameters<FindFilesArgs>,
+        ctx: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let backend = self.backend.clone();
+        let timeout_ms = args.timeout_ms;
+        let result = Self::run_walk(ctx, "find_files_failed", timeout_ms, move |control| {
+            backend.find_files(args, control)
+        })
+        .await?;
+
+        let json = serde_json::to_string(&result)
+            .map_err(|e| Self::internal_error("serialize_failed", e.to_string()))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    #[tool(
+        description = "Summarize the repository: per-language file counts, code/comment/blank lines and bytes, largest files and deepest directories (gitignore aware)"
+    )]
+    pub async fn repo_stats(
+        &self,
+        Parameters(args): Parameters<RepoStatsArgs>,
+        ctx: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let backend = self.backend.clone();
+        let timeout_ms = args.timeout_ms;
+        let result = Self::run_walk(ctx, "repo_stats_failed", timeout_ms, move |control| {
+            backend.repo_stats(args, control)
+        })
+        .await?;
+
+        let json = serde_json::to_string(&result)
+            .map_err(|e| Self::internal_error("serialize_failed", e.to_string()))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    #[tool(description = "List file type names usable in types/exclude_types filters")]
+    pub async fn list_file_types(
+        &self,
+        Parameters(args): Parameters<ListFileTypesArgs>,
     ) -> Result<CallToolResult, McpError> {
         let result = self
             .backend
-            .find_files(args)
-            .map_err(|e| Self::internal_error("find_files_failed", e.to_string()))?;
+            .list_file_types(args)
+            .map_err(|e| Self::internal_error("list_file_types_failed", e.to_string()))?;
 
         let json = serde_json::to_string(&result)
             .map_err(|e| Self::internal_error("serialize_failed", e.to_string()))?;
@@ -219,7 +606,7 @@ impl ServerHandler for FileServer {
     fn get_info(&self) -> ServerInfo {
         ServerInfo {
             instructions: Some(
-                "Fast git-aware file server with tools: search_text, read_file, list_files, find_files, stat, path_info, create_file, overwrite_file, delete_path, copy_path, move_path"
+                "Fast git-aware file server with tools: search_text, search_symbols, search_structural, replace_text, rename_symbol, run_command, read_file, read_files, file_outline, goto_definition, find_references, hover, diagnostics, repo_stats, list_files, find_files, list_file_types, stat, path_info, create_file, overwrite_file, delete_path, copy_path, move_path"
                     .to_string(),
             ),
             protocol_version: ProtocolVersion::V_2024_11_05,
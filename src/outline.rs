@@ -0,0 +1,314 @@
+//! Symbol outlines of source files, extracted from tree-sitter syntax trees.
+
+use tree_sitter::Node;
+
+use crate::syntax::{self, Language};
+use crate::types::{OutlineSymbol, SymbolKind};
+
+/// Longest signature returned, in characters.
+const MAX_SIGNATURE_CHARS: usize = 200;
+
+/// Build the nested symbol outline of `source`.
+pub fn outline(language: Language, source: &str) -> Vec<OutlineSymbol> {
+    let Some(tree) = language.parse(source) else {
+        return Vec::new();
+    };
+    let mut symbols = Vec::new();
+    collect(language, tree.root_node(), source, None, &mut symbols);
+    symbols
+}
+
+/// Find the symbol named by `query` (`"name"` or a `::`/`.` separated path
+/// such as `"Backend::read_file"`) in `symbols`. The path must match the end
+/// of a symbol's full name; the first match in file order wins.
+pub fn find<'a>(symbols: &'a [OutlineSymbol], query: &str) -> Option<&'a OutlineSymbol> {
+    let wanted = name_segments(query);
+    if wanted.is_empty() {
+        return None;
+    }
+
+    fn walk<'a>(
+        symbols: &'a [OutlineSymbol],
+        path: &mut Vec<&'a str>,
+        wanted: &[&str],
+    ) -> Option<&'a OutlineSymbol> {
+        for symbol in symbols {
+            let depth = path.len();
+            // Names may themselves be qualified (`Server.Start`, `Foo::bar`).
+            path.extend(name_segments(&symbol.name));
+            if path.ends_with(wanted) {
+                return Some(symbol);
+            }
+            let found = walk(&symbol.children, path, wanted);
+            path.truncate(depth);
+            if found.is_some() {
+                return found;
+            }
+        }
+        None
+    }
+
+    walk(symbols, &mut Vec::new(), &wanted)
+}
+
+fn name_segments(name: &str) -> Vec<&str> {
+    name.split("::")
+        .flat_map(|part| part.split('.'))
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+/// Drop symbols nested deeper than `max_depth` (1 = top level only).
+pub fn truncate_depth(symbols: &mut [OutlineSymbol], max_depth: u32) {
+    for symbol in symbols {
+        if max_depth <= 1 {
+            symbol.children.clear();
+        } else {
+            truncate_depth(&mut symbol.children, max_depth - 1);
+        }
+    }
+}
+
+/// Walk `node`'s children, appending symbols to `out`. `container` is the
+/// kind of the enclosing symbol, used to tell methods from functions.
+fn collect(
+    language: Language,
+    node: Node<'_>,
+    source: &str,
+    container: Option<SymbolKind>,
+    out: &mut Vec<OutlineSymbol>,
+) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        match symbol_at(language, child, source, container) {
+            Some((mut symbol, inner)) => {
+                collect(
+                    language,
+                    inner,
+                    source,
+                    Some(symbol.kind),
+                    &mut symbol.children,
+                );
+                out.push(symbol);
+            }
+            None => collect(language, child, source, container, out),
+        }
+    }
+}
+
+/// The symbol declared by `node`, if it declares one, and the node to
+/// search for nested symbols.
+fn symbol_at<'t>(
+    language: Language,
+    node: Node<'t>,
+    source: &str,
+    container: Option<SymbolKind>,
+) -> Option<(OutlineSymbol, Node<'t>)> {
+    let in_type = matches!(
+        container,
+        Some(
+            SymbolKind::Class
+                | SymbolKind::Struct
+                | SymbolKind::Interface
+                | SymbolKind::Trait
+                | SymbolKind::Impl
+        )
+    );
+    let function = if in_type {
+        SymbolKind::Method
+    } else {
+        SymbolKind::Function
+    };
+    let field_name = || {
+        node.child_by_field_name("name")
+            .map(|name| syntax::node_text(name, source).to_string())
+    };
+
+    let (kind, name) = match (language, node.kind()) {
+        (Language::Rust, "function_item" | "function_signature_item") => (function, field_name()?),
+        (Language::Rust, "struct_item" | "union_item") => (SymbolKind::Struct, field_name()?),
+        (Language::Rust, "enum_item") => (SymbolKind::Enum, field_name()?),
+        (Language::Rust, "trait_item") => (SymbolKind::Trait, field_name()?),
+        (Language::Rust, "mod_item") => (SymbolKind::Module, field_name()?),
+        (Language::Rust, "const_item" | "static_item") => (SymbolKind::Constant, field_name()?),
+        (Language::Rust, "type_item") => (SymbolKind::Type, field_name()?),
+        (Language::Rust, "macro_definition") => (SymbolKind::Macro, field_name()?),
+        (Language::Rust, "impl_item") => {
+            let ty = syntax::node_text(node.child_by_field_name("type")?, source);
+            let name = match node.child_by_field_name("trait") {
+                Some(tr) => format!("{} for {ty}", syntax::node_text(tr, source)),
+                None => ty.to_string(),
+            };
+            (SymbolKind::Impl, name)
+        }
+
+        (
+            Language::TypeScript | Language::Tsx | Language::JavaScript,
+            "function_declaration" | "generator_function_declaration",
+        ) => (SymbolKind::Function, field_name()?),
+        (
+            Language::TypeScript | Language::Tsx | Language::JavaScript,
+            "class_declaration" | "abstract_class_declaration",
+        ) => (SymbolKind::Class, field_name()?),
+        (
+            Language::TypeScript | Language::Tsx | Language::JavaScript,
+            "method_definition" | "method_signature" | "abstract_method_signature",
+        ) => (SymbolKind::Method, field_name()?),
+        (Language::TypeScript | Language::Tsx | Language::JavaScript, "interface_declaration") => {
+            (SymbolKind::Interface, field_name()?)
+        }
+        (Language::TypeScript | Language::Tsx | Language::JavaScript, "enum_declaration") => {
+            (SymbolKind::Enum, field_name()?)
+        }
+        (Language::TypeScript | Language::Tsx | Language::JavaScript, "type_alias_declaration") => {
+            (SymbolKind::Type, field_name()?)
+        }
+        (
+            Language::TypeScript | Language::Tsx | Language::JavaScript,
+            "internal_module" | "module",
+        ) => (SymbolKind::Module, field_name()?),
+        // `const foo = (...) => ...` and `const foo = function () {...}`.
+        (Language::TypeScript | Language::Tsx | Language::JavaScript, "variable_declarator") => {
+            let value = node.child_by_field_name("value")?;
+            if !matches!(
+                value.kind(),
+                "arrow_function" | "function_expression" | "function"
+            ) {
+                return None;
+            }
+            (function, field_name()?)
+        }
+
+        (Language::Python, "decorated_definition") => {
+            let definition = node.child_by_field_name("definition")?;
+            let (mut symbol, inner) = symbol_at(language, definition, source, container)?;
+            symbol.start_line = syntax::line_range(node).0;
+            return Some((symbol, inner));
+        }
+        (Language::Python, "function_definition") => (function, field_name()?),
+        (Language::Python, "class_definition") => (SymbolKind::Class, field_name()?),
+
+        (Language::Go, "function_declaration") => (SymbolKind::Function, field_name()?),
+        (Language::Go, "method_declaration") => {
+            // Qualify methods with their receiver type, e.g. `Server.Start`.
+            let receiver = node
+                .child_by_field_name("receiver")
+                .and_then(|r| go_receiver_type(r, source));
+            let name = field_name()?;
+            let name = match receiver {
+                Some(receiver) => format!("{receiver}.{name}"),
+                None => name,
+            };
+            (SymbolKind::Method, name)
+        }
+        (Language::Go, "type_spec") => {
+            let kind = match node.child_by_field_name("type").map(|t| t.kind()) {
+                Some("struct_type") => SymbolKind::Struct,
+                Some("interface_type") => SymbolKind::Interface,
+                _ => SymbolKind::Type,
+            };
+            (kind, field_name()?)
+        }
+        (Language::Go, "const_spec") => (SymbolKind::Constant, field_name()?),
+
+        (Language::C | Language::Cpp, "function_definition") => {
+            (function, declarator_name(node, source)?)
+        }
+        (Language::C | Language::Cpp, "struct_specifier" | "union_specifier") => {
+            node.child_by_field_name("body")?;
+            (SymbolKind::Struct, field_name()?)
+        }
+        (Language::C | Language::Cpp, "enum_specifier") => {
+            node.child_by_field_name("body")?;
+            (SymbolKind::Enum, field_name()?)
+        }
+        (Language::C | Language::Cpp, "type_definition") => {
+            (SymbolKind::Type, declarator_name(node, source)?)
+        }
+        (Language::C | Language::Cpp, "preproc_def" | "preproc_function_def") => {
+            (SymbolKind::Macro, field_name()?)
+        }
+        (Language::Cpp, "class_specifier") => {
+            node.child_by_field_name("body")?;
+            (SymbolKind::Class, field_name()?)
+        }
+        (Language::Cpp, "namespace_definition") => (SymbolKind::Module, field_name()?),
+        (Language::Cpp, "alias_declaration") => (SymbolKind::Type, field_name()?),
+
+        (Language::Java, "class_declaration" | "record_declaration") => {
+            (SymbolKind::Class, field_name()?)
+        }
+        (Language::Java, "interface_declaration" | "annotation_type_declaration") => {
+            (SymbolKind::Interface, field_name()?)
+        }
+        (Language::Java, "enum_declaration") => (SymbolKind::Enum, field_name()?),
+        (Language::Java, "method_declaration" | "constructor_declaration") => {
+            (SymbolKind::Method, field_name()?)
+        }
+
+        _ => return None,
+    };
+
+    let (start_line, end_line) = syntax::line_range(node);
+    let symbol = OutlineSymbol {
+        kind,
+        name,
+        start_line,
+        end_line,
+        signature: signature(node, source),
+        children: Vec::new(),
+    };
+    Some((symbol, node))
+}
+
+/// Name of a C/C++ declaration: follow the `declarator` chain (pointer,
+/// function, array declarators, ...) down to the identifier.
+fn declarator_name(node: Node<'_>, source: &str) -> Option<String> {
+    let mut current = node.child_by_field_name("declarator")?;
+    while let Some(inner) = current.child_by_field_name("declarator") {
+        current = inner;
+    }
+    Some(syntax::node_text(current, source).to_string())
+}
+
+/// Type name of a Go method receiver such as `(s *Server)`.
+fn go_receiver_type(receiver: Node<'_>, source: &str) -> Option<String> {
+    let mut cursor = receiver.walk();
+    let param = receiver
+        .named_children(&mut cursor)
+        .find(|n| n.kind() == "parameter_declaration")?;
+    let ty = syntax::node_text(param.child_by_field_name("type")?, source);
+    Some(ty.trim_start_matches('*').to_string())
+}
+
+/// Declaration text up to the start of its body (or value), with whitespace
+/// collapsed. Declarations without either are cut at the end of the line.
+fn signature(node: Node<'_>, source: &str) -> String {
+    // For `const f = (...) => {...}` stop at the function's body rather
+    // than at the value.
+    let value = node
+        .child_by_field_name("value")
+        .map(|value| value.child_by_field_name("body").unwrap_or(value));
+    let end = match node.child_by_field_name("body").or(value) {
+        Some(body) => body.start_byte(),
+        None => {
+            let text = &source[node.byte_range()];
+            node.start_byte() + text.find('\n').unwrap_or(text.len())
+        }
+    };
+    let text = &source[node.start_byte()..end];
+
+    let mut signature = String::new();
+    for word in text.split_whitespace() {
+        if !signature.is_empty() {
+            signature.push(' ');
+        }
+        signature.push_str(word);
+    }
+    let signature = signature.trim_end_matches([' ', '{', ':', '=']).trim_end();
+
+    match signature.char_indices().nth(MAX_SIGNATURE_CHARS) {
+        Some((cut, _)) => format!("{}...", &signature[..cut]),
+        None => signature.to_string(),
+    }
+}
@@ -0,0 +1,113 @@
+//! Relevance scoring for ranked `search_text` results.
+
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use std::time::SystemTime;
+
+use regex::Regex;
+
+use crate::types::SearchHit;
+
+const DEFINITION_BONUS: f64 = 10.0;
+const DEFINITION_LINE_BONUS: f64 = 2.0;
+const DENSITY_WEIGHT: f64 = 5.0;
+const DEPTH_PENALTY: f64 = 0.5;
+const TEST_PENALTY: f64 = 3.0;
+const RECENCY_WEIGHT: f64 = 2.0;
+const RECENCY_HALF_LIFE_DAYS: f64 = 30.0;
+
+/// Lines that look like they introduce a named item in common languages,
+/// e.g. `pub fn foo`, `class Foo`, `def foo`, `export const foo`. The first
+/// capture group is the defined name.
+static DEFINITION_LINE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^\s*(?:(?:pub(?:\([^)]*\))?|export|default|async|static|public|private|protected|internal|abstract|final|unsafe|extern|override|virtual|inline)\s+)*(?:fn|struct|enum|union|trait|impl|type|mod|macro_rules!|const|static|class|interface|def|function|func|let|var|val|module|namespace|typedef|#define)\s+(?:mut\s+)?([A-Za-z_$][\w$]*)",
+    )
+    .expect("valid definition regex")
+});
+
+/// Per-file signals used when scoring hits.
+pub struct FileInfo {
+    pub line_count: u64,
+    /// Last modified time as UNIX timestamp seconds.
+    pub modified: Option<u64>,
+}
+
+/// Score every hit, then sort by descending score (ties broken by path and
+/// line so results stay deterministic). `file_info` is called once per file.
+pub fn rank_hits(hits: &mut [SearchHit], file_info: impl Fn(&str) -> FileInfo) {
+    let mut hits_per_file: HashMap<String, u64> = HashMap::new();
+    for hit in hits.iter() {
+        *hits_per_file.entry(hit.path.clone()).or_default() += 1;
+    }
+
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let mut infos: HashMap<String, FileInfo> = HashMap::new();
+    for hit in hits.iter_mut() {
+        let info = infos
+            .entry(hit.path.clone())
+            .or_insert_with(|| file_info(&hit.path));
+        let file_hits = hits_per_file.get(&hit.path).copied().unwrap_or(1);
+        hit.score = Some(score_hit(hit, file_hits, info, now));
+    }
+
+    hits.sort_by(|a, b| {
+        b.score
+            .unwrap_or(0.0)
+            .total_cmp(&a.score.unwrap_or(0.0))
+            .then_with(|| a.path.cmp(&b.path))
+            .then_with(|| a.line.cmp(&b.line))
+    });
+}
+
+fn score_hit(hit: &SearchHit, file_hits: u64, info: &FileInfo, now: u64) -> f64 {
+    let mut score = 0.0;
+
+    if !hit.binary
+        && let Some(caps) = DEFINITION_LINE.captures(&hit.line_text)
+    {
+        // Full bonus only when the match is the name being defined, so the
+        // definition ranks above call sites that merely sit on such a line.
+        let column = hit.column as usize;
+        score += match caps.get(1) {
+            Some(name) if name.range().contains(&column) => DEFINITION_BONUS,
+            _ => DEFINITION_LINE_BONUS,
+        };
+    }
+
+    // Files where the query is a large share of the content are more likely
+    // to be about it; saturate so one dense file cannot dominate.
+    let density = file_hits as f64 / info.line_count.max(1) as f64;
+    score += DENSITY_WEIGHT * (density * 20.0).min(1.0);
+
+    let depth = hit.path.matches(['/', '\\']).count();
+    score -= DEPTH_PENALTY * depth as f64;
+
+    if is_test_path(&hit.path) {
+        score -= TEST_PENALTY;
+    }
+
+    if let Some(modified) = info.modified {
+        let age_days = now.saturating_sub(modified) as f64 / 86_400.0;
+        score += RECENCY_WEIGHT * (-age_days / RECENCY_HALF_LIFE_DAYS).exp2();
+    }
+
+    score
+}
+
+fn is_test_path(path: &str) -> bool {
+    let lower = path.to_lowercase();
+    lower.split(['/', '\\']).any(|segment| {
+        matches!(
+            segment,
+            "test" | "tests" | "spec" | "specs" | "__tests__" | "testdata" | "fixtures"
+        ) || segment.starts_with("test_")
+            || segment.contains("_test.")
+            || segment.contains(".test.")
+            || segment.contains(".spec.")
+    })
+}
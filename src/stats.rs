@@ -0,0 +1,229 @@
+//! Language detection and code/comment/blank line counting for
+//! `repo_stats`, in the spirit of tokei and cloc.
+//!
+//! Lines are classified from comment markers only: string literals are not
+//! parsed, so a marker inside a string can be miscounted, and docstrings
+//! count as code.
+
+use std::io::{self, BufRead};
+use std::path::Path;
+
+/// Comment syntax of a counted language.
+pub struct LanguageDef {
+    pub name: &'static str,
+    extensions: &'static [&'static str],
+    /// Exact file names, for files without a telling extension.
+    file_names: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    block_comments: &'static [(&'static str, &'static str)],
+}
+
+const C_BLOCK: &[(&str, &str)] = &[("/*", "*/")];
+const HTML_BLOCK: &[(&str, &str)] = &[("<!--", "-->")];
+
+const fn lang(
+    name: &'static str,
+    extensions: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    block_comments: &'static [(&'static str, &'static str)],
+) -> LanguageDef {
+    LanguageDef {
+        name,
+        extensions,
+        file_names: &[],
+        line_comments,
+        block_comments,
+    }
+}
+
+static LANGUAGES: &[LanguageDef] = &[
+    lang("Rust", &["rs"], &["//"], C_BLOCK),
+    lang("TypeScript", &["ts", "mts", "cts"], &["//"], C_BLOCK),
+    lang("TSX", &["tsx"], &["//"], C_BLOCK),
+    lang("JavaScript", &["js", "mjs", "cjs"], &["//"], C_BLOCK),
+    lang("JSX", &["jsx"], &["//"], C_BLOCK),
+    lang("Python", &["py", "pyi", "pyw"], &["#"], &[]),
+    lang("Go", &["go"], &["//"], C_BLOCK),
+    lang("C", &["c"], &["//"], C_BLOCK),
+    lang("C Header", &["h"], &["//"], C_BLOCK),
+    lang(
+        "C++",
+        &["cc", "cpp", "cxx", "c++", "hh", "hpp", "hxx", "inl"],
+        &["//"],
+        C_BLOCK,
+    ),
+    lang("Objective-C", &["m", "mm"], &["//"], C_BLOCK),
+    lang("C#", &["cs"], &["//"], C_BLOCK),
+    lang("Java", &["java"], &["//"], C_BLOCK),
+    lang("Kotlin", &["kt", "kts"], &["//"], C_BLOCK),
+    lang("Scala", &["scala", "sc"], &["//"], C_BLOCK),
+    lang("Swift", &["swift"], &["//"], C_BLOCK),
+    lang("Dart", &["dart"], &["//"], C_BLOCK),
+    lang("Zig", &["zig"], &["//"], &[]),
+    lang("PHP", &["php"], &["//", "#"], C_BLOCK),
+    lang(
+        "Ruby",
+        &["rb", "rake", "gemspec"],
+        &["#"],
+        &[("=begin", "=end")],
+    ),
+    lang("Perl", &["pl", "pm"], &["#"], &[]),
+    lang("Lua", &["lua"], &["--"], &[("--[[", "]]")]),
+    lang("Haskell", &["hs"], &["--"], &[("{-", "-}")]),
+    lang("OCaml", &["ml", "mli"], &[], &[("(*", "*)")]),
+    lang("Elixir", &["ex", "exs"], &["#"], &[]),
+    lang("Erlang", &["erl", "hrl"], &["%"], &[]),
+    lang("Clojure", &["clj", "cljs", "cljc", "edn"], &[";"], &[]),
+    lang("R", &["r"], &["#"], &[]),
+    lang("Julia", &["jl"], &["#"], &[("#=", "=#")]),
+    lang("Nim", &["nim"], &["#"], &[("#[", "]#")]),
+    lang("Shell", &["sh", "bash", "zsh", "fish"], &["#"], &[]),
+    lang("PowerShell", &["ps1", "psm1"], &["#"], &[("<#", "#>")]),
+    lang("Batch", &["bat", "cmd"], &["REM", "rem", "::"], &[]),
+    lang("SQL", &["sql"], &["--"], C_BLOCK),
+    lang("HTML", &["html", "htm"], &[], HTML_BLOCK),
+    lang(
+        "XML",
+        &["xml", "xsd", "xsl", "svg", "plist"],
+        &[],
+        HTML_BLOCK,
+    ),
+    lang("CSS", &["css"], &[], C_BLOCK),
+    lang("SCSS", &["scss", "sass"], &["//"], C_BLOCK),
+    lang("Less", &["less"], &["//"], C_BLOCK),
+    lang("Vue", &["vue"], &["//"], &[("<!--", "-->"), ("/*", "*/")]),
+    lang(
+        "Svelte",
+        &["svelte"],
+        &["//"],
+        &[("<!--", "-->"), ("/*", "*/")],
+    ),
+    lang("GraphQL", &["graphql", "gql"], &["#"], &[]),
+    lang("Protobuf", &["proto"], &["//"], C_BLOCK),
+    lang("Terraform", &["tf", "tfvars", "hcl"], &["#", "//"], C_BLOCK),
+    lang("Nix", &["nix"], &["#"], C_BLOCK),
+    lang("JSON", &["json", "jsonc", "json5"], &["//"], C_BLOCK),
+    lang("TOML", &["toml"], &["#"], &[]),
+    lang("YAML", &["yaml", "yml"], &["#"], &[]),
+    lang("INI", &["ini", "cfg"], &[";", "#"], &[]),
+    lang("Markdown", &["md", "markdown"], &[], HTML_BLOCK),
+    lang("reStructuredText", &["rst"], &[], &[]),
+    lang("Plain Text", &["txt"], &[], &[]),
+    LanguageDef {
+        name: "Makefile",
+        extensions: &["mk", "mak"],
+        file_names: &["makefile", "gnumakefile"],
+        line_comments: &["#"],
+        block_comments: &[],
+    },
+    LanguageDef {
+        name: "CMake",
+        extensions: &["cmake"],
+        file_names: &["cmakelists.txt"],
+        line_comments: &["#"],
+        block_comments: &[],
+    },
+    LanguageDef {
+        name: "Dockerfile",
+        extensions: &["dockerfile"],
+        file_names: &["dockerfile", "containerfile"],
+        line_comments: &["#"],
+        block_comments: &[],
+    },
+];
+
+/// The counted language of `path`, by file name first, then extension.
+pub fn language_for(path: &Path) -> Option<&'static LanguageDef> {
+    let file_name = path.file_name()?.to_str()?.to_ascii_lowercase();
+    if let Some(def) = LANGUAGES
+        .iter()
+        .find(|def| def.file_names.contains(&file_name.as_str()))
+    {
+        return Some(def);
+    }
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|def| def.extensions.contains(&extension.as_str()))
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LineCounts {
+    pub code: u64,
+    pub comment: u64,
+    pub blank: u64,
+}
+
+/// Count the lines of `reader` as `language`.
+pub fn count_lines(language: &LanguageDef, mut reader: impl BufRead) -> io::Result<LineCounts> {
+    let mut counts = LineCounts::default();
+    // Index into `block_comments` of the comment the line starts inside.
+    let mut in_block: Option<usize> = None;
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        let line = String::from_utf8_lossy(&buf);
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            counts.blank += 1;
+            continue;
+        }
+
+        let mut rest = trimmed;
+        let mut code = false;
+        loop {
+            if let Some(index) = in_block {
+                let (_, end) = language.block_comments[index];
+                match rest.find(end) {
+                    Some(at) => {
+                        rest = rest[at + end.len()..].trim_start();
+                        in_block = None;
+                    }
+                    None => break,
+                }
+            }
+            if rest.is_empty() {
+                break;
+            }
+            // Block markers first: Lua's `--[[` also starts a line comment.
+            if let Some(index) = language
+                .block_comments
+                .iter()
+                .position(|(start, _)| rest.starts_with(start))
+            {
+                rest = &rest[language.block_comments[index].0.len()..];
+                in_block = Some(index);
+                continue;
+            }
+            if language
+                .line_comments
+                .iter()
+                .any(|marker| rest.starts_with(marker))
+            {
+                break;
+            }
+            // Code up to the next block comment, if any, which may stay
+            // open past the end of the line.
+            code = true;
+            match language
+                .block_comments
+                .iter()
+                .filter_map(|(start, _)| rest.find(start))
+                .min()
+            {
+                Some(at) => rest = &rest[at..],
+                None => break,
+            }
+        }
+
+        if code {
+            counts.code += 1;
+        } else {
+            counts.comment += 1;
+        }
+    }
+    Ok(counts)
+}
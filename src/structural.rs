@@ -0,0 +1,367 @@
+//! Structural (AST pattern) matching for `search_structural`.
+//!
+//! Patterns are code snippets in the target language with metavariables, in
+//! the style of ast-grep: `$X` matches any single node, `$$$ARGS` matches any
+//! sequence of sibling nodes (possibly empty) and `$_` matches without
+//! capturing. A metavariable used twice must match the same text both times.
+//!
+//! `$` is not an identifier character in most languages, so metavariables
+//! are rewritten to identifiers starting with [`EXPANDO`] before the pattern
+//! is parsed with the same tree-sitter grammar as the files it is matched
+//! against.
+
+use std::collections::{BTreeMap, HashSet};
+use std::ops::Range;
+use std::sync::LazyLock;
+
+use regex::{Captures as RegexCaptures, Regex};
+use tree_sitter::{Node, Tree};
+
+use crate::syntax::Language;
+
+/// Identifier prefix standing in for `$` inside parsed patterns.
+const EXPANDO: char = 'µ';
+
+/// `$NAME`, `$$$NAME`, `$$$` and `$_` in pattern and rewrite text.
+static METAVAR: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\$\$\$([A-Z_][A-Z0-9_]*)?|\$([A-Z_][A-Z0-9_]*)").expect("valid metavariable regex")
+});
+
+/// Metavariable name to captured source text.
+pub type Captures = BTreeMap<String, String>;
+
+/// Metavariable name to the byte range it matched, while matching.
+type Bindings = BTreeMap<String, Range<usize>>;
+
+/// A pattern parsed for one language.
+pub struct Pattern {
+    source: String,
+    tree: Tree,
+    /// Child indices from the tree root down to the pattern node.
+    path: Vec<usize>,
+}
+
+/// One match of a pattern in a file.
+pub struct StructuralMatch {
+    pub start_byte: usize,
+    pub end_byte: usize,
+    /// 0-based row and column (in bytes) of the match start.
+    pub start: (usize, usize),
+    /// 0-based row of the match end.
+    pub end_row: usize,
+    pub captures: Captures,
+    /// Byte range of each capture in the source.
+    capture_ranges: Bindings,
+}
+
+impl Pattern {
+    /// Parse `pattern` for `language`. Snippets that are not valid on their
+    /// own at the top level of a file (e.g. statements in C or Java) are
+    /// retried inside a function body. Returns `None` if no attempt parses
+    /// without errors.
+    pub fn parse(language: Language, pattern: &str) -> Option<Self> {
+        let pattern = pattern.trim();
+        if pattern.is_empty() {
+            return None;
+        }
+        let expanded = METAVAR.replace_all(pattern, |caps: &RegexCaptures<'_>| {
+            let (prefix, name) = match (caps.get(1), caps.get(2)) {
+                (_, Some(name)) => (EXPANDO.to_string(), name.as_str()),
+                (Some(name), None) => (EXPANDO.to_string().repeat(3), name.as_str()),
+                (None, None) => (EXPANDO.to_string().repeat(3), ""),
+            };
+            format!("{prefix}{name}")
+        });
+
+        for wrapper in wrappers(language) {
+            let (before, after) = wrapper.split_once("{}").unwrap_or(("", ""));
+            let source = format!("{before}{expanded}{after}");
+            let Some(tree) = language.parse(&source) else {
+                continue;
+            };
+            let range = (before.len(), before.len() + expanded.len());
+            let Some(node) = tree
+                .root_node()
+                .named_descendant_for_byte_range(range.0, range.1)
+            else {
+                continue;
+            };
+            if node.has_error() || node.start_byte() != range.0 || node.end_byte() != range.1 {
+                continue;
+            }
+
+            let path = path_to(node);
+            return Some(Self { source, tree, path });
+        }
+        None
+    }
+
+    fn root(&self) -> Node<'_> {
+        let mut node = self.tree.root_node();
+        for &index in &self.path {
+            node = node.child(index as u32).expect("pattern path is valid");
+        }
+        node
+    }
+
+    /// All matches of the pattern in the tree of `source`, in document order.
+    /// Nested matches are included.
+    pub fn find_all(&self, tree: &Tree, source: &str) -> Vec<StructuralMatch> {
+        let pattern = self.root();
+        let any_kind = self.metavar(pattern).is_some();
+
+        let mut matches = Vec::new();
+        let mut cursor = tree.walk();
+        loop {
+            let node = cursor.node();
+            if node.is_named() && (any_kind || node.kind_id() == pattern.kind_id()) {
+                let mut bindings = Bindings::new();
+                if self.match_node(pattern, node, source, &mut bindings) {
+                    let captures = bindings
+                        .iter()
+                        .map(|(name, range)| (name.clone(), source[range.clone()].to_string()))
+                        .collect();
+                    matches.push(StructuralMatch {
+                        start_byte: node.start_byte(),
+                        end_byte: node.end_byte(),
+                        start: (node.start_position().row, node.start_position().column),
+                        end_row: node.end_position().row,
+                        captures,
+                        capture_ranges: bindings,
+                    });
+                }
+            }
+
+            if cursor.goto_first_child() {
+                continue;
+            }
+            loop {
+                if cursor.goto_next_sibling() {
+                    break;
+                }
+                if !cursor.goto_parent() {
+                    return matches;
+                }
+            }
+        }
+    }
+
+    /// The metavariable name if `node` is exactly a single-node metavariable
+    /// (`"_"` for the wildcard).
+    fn metavar(&self, node: Node<'_>) -> Option<&str> {
+        let name = self.source[node.byte_range()].strip_prefix(EXPANDO)?;
+        let is_name = !name.is_empty()
+            && !name.starts_with(EXPANDO)
+            && name
+                .chars()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+        is_name.then_some(name)
+    }
+
+    /// The name of a `$$$` sequence metavariable (empty when anonymous).
+    fn multi_metavar(&self, node: Node<'_>) -> Option<&str> {
+        let name = self.source[node.byte_range()].strip_prefix(&EXPANDO.to_string().repeat(3))?;
+        name.chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+            .then_some(name)
+    }
+
+    fn match_node(
+        &self,
+        pattern: Node<'_>,
+        target: Node<'_>,
+        source: &str,
+        captures: &mut Bindings,
+    ) -> bool {
+        if let Some(name) = self.metavar(pattern) {
+            return bind(captures, name, source, target.byte_range());
+        }
+        if pattern.kind_id() != target.kind_id() {
+            return false;
+        }
+
+        let pattern_children = children(pattern);
+        if pattern_children.is_empty() {
+            return self.source[pattern.byte_range()] == source[target.byte_range()];
+        }
+        self.match_seq(&pattern_children, &children(target), source, captures)
+    }
+
+    fn match_seq(
+        &self,
+        patterns: &[Node<'_>],
+        targets: &[Node<'_>],
+        source: &str,
+        captures: &mut Bindings,
+    ) -> bool {
+        let Some((first, rest)) = patterns.split_first() else {
+            return targets.is_empty();
+        };
+
+        if let Some(name) = self.multi_metavar(*first) {
+            for take in 0..=targets.len() {
+                let range = match (targets.first(), take.checked_sub(1).map(|i| targets[i])) {
+                    (Some(start), Some(end)) => start.start_byte()..end.end_byte(),
+                    _ => 0..0,
+                };
+                let mut attempt = captures.clone();
+                if bind(&mut attempt, name, source, range)
+                    && self.match_seq(rest, &targets[take..], source, &mut attempt)
+                {
+                    *captures = attempt;
+                    return true;
+                }
+            }
+            return false;
+        }
+
+        let Some((target, targets)) = targets.split_first() else {
+            return false;
+        };
+        let mut attempt = captures.clone();
+        if self.match_node(*first, *target, source, &mut attempt)
+            && self.match_seq(rest, targets, source, &mut attempt)
+        {
+            *captures = attempt;
+            return true;
+        }
+        false
+    }
+}
+
+/// Substitute captured text for `$NAME` / `$$$NAME` in a rewrite template.
+/// Unknown metavariables are left as written.
+pub fn expand_template(template: &str, captures: &Captures) -> String {
+    METAVAR
+        .replace_all(template, |caps: &RegexCaptures<'_>| {
+            let name = caps.get(2).or(caps.get(1)).map_or("", |m| m.as_str());
+            captures
+                .get(name)
+                .cloned()
+                .unwrap_or_else(|| caps[0].to_string())
+        })
+        .into_owned()
+}
+
+/// Replace every match in `source` with `template`. Matches nested inside
+/// another match's captures are rewritten first, so `$X.unwrap()` →
+/// `$X?` turns `a.unwrap().unwrap()` into `a??`. `matches` must be in
+/// document order, as returned by [`Pattern::find_all`]. Returns the new
+/// source and the number of matches replaced; a nested match only counts
+/// when the template keeps the capture it sits in.
+pub fn rewrite(source: &str, matches: &[StructuralMatch], template: &str) -> (String, u64) {
+    let used: HashSet<&str> = METAVAR
+        .captures_iter(template)
+        .filter_map(|caps| caps.get(2).or(caps.get(1)))
+        .map(|name| name.as_str())
+        .collect();
+    let mut count = 0;
+    let replaced = rewrite_range(
+        source,
+        0..source.len(),
+        matches,
+        template,
+        &used,
+        &mut count,
+    );
+    (replaced, count)
+}
+
+/// `source[range]` with the outermost `matches` inside it rewritten. A
+/// match's descendants come after it in document order, so recursing into
+/// its captures only considers later matches and always terminates.
+fn rewrite_range(
+    source: &str,
+    range: Range<usize>,
+    matches: &[StructuralMatch],
+    template: &str,
+    used: &HashSet<&str>,
+    count: &mut u64,
+) -> String {
+    let mut out = String::with_capacity(range.len());
+    let mut last = range.start;
+    for (i, mat) in matches.iter().enumerate() {
+        if mat.start_byte < last || mat.end_byte > range.end {
+            continue;
+        }
+        // Captures the template drops are not rewritten (or counted).
+        let captures = mat
+            .capture_ranges
+            .iter()
+            .filter(|(name, _)| used.contains(name.as_str()))
+            .map(|(name, capture)| {
+                let text = rewrite_range(
+                    source,
+                    capture.clone(),
+                    &matches[i + 1..],
+                    template,
+                    used,
+                    count,
+                );
+                (name.clone(), text)
+            })
+            .collect();
+        out.push_str(&source[last..mat.start_byte]);
+        out.push_str(&expand_template(template, &captures));
+        *count += 1;
+        last = mat.end_byte;
+    }
+    out.push_str(&source[last..range.end]);
+    out
+}
+
+/// Record the text at `range` for metavariable `name`, or check it against
+/// an earlier capture. `_` and anonymous `$$$` never capture.
+fn bind(captures: &mut Bindings, name: &str, source: &str, range: Range<usize>) -> bool {
+    if name.is_empty() || name == "_" {
+        return true;
+    }
+    match captures.get(name) {
+        Some(previous) => source[previous.clone()] == source[range],
+        None => {
+            captures.insert(name.to_string(), range);
+            true
+        }
+    }
+}
+
+/// Children that take part in matching: everything but comments and other
+/// extras, and zero-width nodes the parser inserted to recover.
+fn children(node: Node<'_>) -> Vec<Node<'_>> {
+    let mut cursor = node.walk();
+    node.children(&mut cursor)
+        .filter(|child| !child.is_extra() && !child.is_missing())
+        .collect()
+}
+
+fn path_to(node: Node<'_>) -> Vec<usize> {
+    let mut path = Vec::new();
+    let mut current = node;
+    while let Some(parent) = current.parent() {
+        let mut cursor = parent.walk();
+        let index = parent
+            .children(&mut cursor)
+            .position(|child| child == current)
+            .expect("node is a child of its parent");
+        path.push(index);
+        current = parent;
+    }
+    path.reverse();
+    path
+}
+
+/// Contexts to try parsing a pattern in, `{}` standing for the pattern.
+fn wrappers(language: Language) -> &'static [&'static str] {
+    match language {
+        Language::Rust => &["{}", "fn µ() { {} }"],
+        Language::C | Language::Cpp => &["{}", "{};", "void µ() { {} }", "void µ() { {}; }"],
+        Language::Go => &["{}", "func µ() { {} }"],
+        Language::Java => &[
+            "{}",
+            "{};",
+            "class µ { void µ() { {} } }",
+            "class µ { void µ() { {}; } }",
+        ],
+        Language::TypeScript | Language::Tsx | Language::JavaScript | Language::Python => &["{}"],
+    }
+}
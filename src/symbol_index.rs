@@ -0,0 +1,149 @@
+//! In-memory index of symbol definitions for `search_symbols`.
+//!
+//! Files are parsed on first use and re-parsed only when their size or
+//! modification time changes, so repeated searches over a repository only
+//! pay for the files edited in between. The least recently used entries are
+//! dropped once the index holds more than `MAX_INDEXED_FILES` files.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use std::{fs, io};
+
+use crate::content;
+use crate::outline;
+use crate::syntax::{self, Language};
+use crate::types::{OutlineSymbol, SymbolKind};
+
+/// Files kept in the index; deleted files and searches over other trees
+/// would otherwise make it grow for the lifetime of the server.
+const MAX_INDEXED_FILES: usize = 50_000;
+
+/// A symbol definition flattened out of a file outline.
+pub struct IndexedSymbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub start_line: u64,
+    pub end_line: u64,
+    /// Qualified name of the enclosing symbols, e.g. `Backend` or
+    /// `ns::Widget`.
+    pub container: Option<String>,
+    pub signature: String,
+}
+
+struct IndexedFile {
+    size: u64,
+    modified: Option<SystemTime>,
+    symbols: Arc<Vec<IndexedSymbol>>,
+    /// Value of [`Files::clock`] when the entry was last returned.
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Files {
+    entries: HashMap<PathBuf, IndexedFile>,
+    /// Incremented on every lookup.
+    clock: u64,
+}
+
+impl Files {
+    /// Drop the least recently used quarter of the entries once there are
+    /// more than [`MAX_INDEXED_FILES`], so eviction is not paid per insert.
+    fn evict(&mut self) {
+        if self.entries.len() <= MAX_INDEXED_FILES {
+            return;
+        }
+        let mut ages: Vec<u64> = self.entries.values().map(|f| f.last_used).collect();
+        let keep = MAX_INDEXED_FILES * 3 / 4;
+        let (_, &mut cutoff, _) = ages.select_nth_unstable(self.entries.len() - keep);
+        self.entries.retain(|_, f| f.last_used >= cutoff);
+    }
+}
+
+#[derive(Default)]
+pub struct SymbolIndex {
+    files: Mutex<Files>,
+}
+
+impl SymbolIndex {
+    /// Symbols defined in the file at `path`, parsing it unless an up to
+    /// date entry is cached. Binary and oversized files have no symbols.
+    pub fn symbols(&self, path: &Path, language: Language) -> io::Result<Arc<Vec<IndexedSymbol>>> {
+        let meta = fs::metadata(path)?;
+        let size = meta.len();
+        let modified = meta.modified().ok();
+
+        {
+            let mut files = self.lock();
+            files.clock += 1;
+            let clock = files.clock;
+            if let Some(entry) = files.entries.get_mut(path)
+                && entry.size == size
+                && entry.modified == modified
+            {
+                entry.last_used = clock;
+                return Ok(entry.symbols.clone());
+            }
+        }
+
+        // Parse outside the lock so other walker threads are not blocked.
+        let symbols = if size > syntax::MAX_PARSE_BYTES {
+            Vec::new()
+        } else {
+            match content::decode_text(&fs::read(path)?) {
+                Some(source) => flatten(language, outline::outline(language, &source)),
+                None => Vec::new(),
+            }
+        };
+        let symbols = Arc::new(symbols);
+
+        let mut files = self.lock();
+        files.clock += 1;
+        let last_used = files.clock;
+        files.entries.insert(
+            path.to_path_buf(),
+            IndexedFile {
+                size,
+                modified,
+                symbols: symbols.clone(),
+                last_used,
+            },
+        );
+        files.evict();
+        Ok(symbols)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Files> {
+        self.files.lock().expect("symbol index mutex poisoned")
+    }
+}
+
+fn flatten(language: Language, symbols: Vec<OutlineSymbol>) -> Vec<IndexedSymbol> {
+    fn walk(
+        separator: &str,
+        symbols: Vec<OutlineSymbol>,
+        container: Option<&str>,
+        out: &mut Vec<IndexedSymbol>,
+    ) {
+        for symbol in symbols {
+            let qualified = match container {
+                Some(container) => format!("{container}{separator}{}", symbol.name),
+                None => symbol.name.clone(),
+            };
+            out.push(IndexedSymbol {
+                name: symbol.name,
+                kind: symbol.kind,
+                start_line: symbol.start_line,
+                end_line: symbol.end_line,
+                container: container.map(str::to_string),
+                signature: symbol.signature,
+            });
+            walk(separator, symbol.children, Some(&qualified), out);
+        }
+    }
+
+    let mut out = Vec::new();
+    walk(language.path_separator(), symbols, None, &mut out);
+    out
+}
@@ -0,0 +1,124 @@
+//! Language detection and tree-sitter parsing for source files.
+
+use std::path::Path;
+
+use tree_sitter::{Node, Parser, Tree};
+
+/// Largest source file parsed, for outlines, symbol search and reads.
+pub const MAX_PARSE_BYTES: u64 = 8 * 1024 * 1024;
+
+/// Languages with a bundled tree-sitter grammar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Language {
+    Rust,
+    TypeScript,
+    Tsx,
+    JavaScript,
+    Python,
+    Go,
+    C,
+    Cpp,
+    Java,
+}
+
+impl Language {
+    /// Detect the language from the file extension.
+    pub fn for_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        Some(match ext.as_str() {
+            "rs" => Self::Rust,
+            "ts" | "mts" | "cts" => Self::TypeScript,
+            "tsx" => Self::Tsx,
+            "js" | "jsx" | "mjs" | "cjs" => Self::JavaScript,
+            "py" | "pyi" => Self::Python,
+            "go" => Self::Go,
+            "c" => Self::C,
+            // `.h` is shared by C and C++; the C++ grammar accepts both.
+            "h" | "hh" | "hpp" | "hxx" | "cc" | "cpp" | "cxx" | "c++" => Self::Cpp,
+            "java" => Self::Java,
+            _ => return None,
+        })
+    }
+
+    /// Every supported language, in the order patterns are tried.
+    pub const ALL: [Self; 9] = [
+        Self::Rust,
+        Self::TypeScript,
+        Self::Tsx,
+        Self::JavaScript,
+        Self::Python,
+        Self::Go,
+        Self::C,
+        Self::Cpp,
+        Self::Java,
+    ];
+
+    /// Look a language up by its [`name`](Self::name), ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|language| language.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Rust => "rust",
+            Self::TypeScript => "typescript",
+            Self::Tsx => "tsx",
+            Self::JavaScript => "javascript",
+            Self::Python => "python",
+            Self::Go => "go",
+            Self::C => "c",
+            Self::Cpp => "cpp",
+            Self::Java => "java",
+        }
+    }
+
+    /// Separator used to qualify nested names, e.g. `Foo::bar` / `Foo.bar`.
+    pub fn path_separator(self) -> &'static str {
+        match self {
+            Self::Rust | Self::C | Self::Cpp => "::",
+            _ => ".",
+        }
+    }
+
+    fn grammar(self) -> tree_sitter::Language {
+        match self {
+            Self::Rust => tree_sitter_rust::LANGUAGE.into(),
+            Self::TypeScript => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+            // JavaScript (including JSX) parses with the TSX grammar.
+            Self::Tsx | Self::JavaScript => tree_sitter_typescript::LANGUAGE_TSX.into(),
+            Self::Python => tree_sitter_python::LANGUAGE.into(),
+            Self::Go => tree_sitter_go::LANGUAGE.into(),
+            Self::C => tree_sitter_c::LANGUAGE.into(),
+            Self::Cpp => tree_sitter_cpp::LANGUAGE.into(),
+            Self::Java => tree_sitter_java::LANGUAGE.into(),
+        }
+    }
+
+    /// Parse `source` into a syntax tree. Syntax errors do not fail the
+    /// parse; they show up as `ERROR` / missing nodes in the tree.
+    pub fn parse(self, source: &str) -> Option<Tree> {
+        let mut parser = Parser::new();
+        parser.set_language(&self.grammar()).ok()?;
+        parser.parse(source, None)
+    }
+}
+
+/// Source text covered by `node`.
+pub fn node_text<'a>(node: Node<'_>, source: &'a str) -> &'a str {
+    &source[node.byte_range()]
+}
+
+/// 1-based inclusive line range of `node`. A node ending at column 0 (after
+/// a trailing newline) does not count that last line.
+pub fn line_range(node: Node<'_>) -> (u64, u64) {
+    let start = node.start_position().row as u64 + 1;
+    let end = node.end_position();
+    let end_row = if end.column == 0 && end.row > node.start_position().row {
+        end.row - 1
+    } else {
+        end.row
+    };
+    (start, end_row as u64 + 1)
+}
@@ -0,0 +1,75 @@
+//! Local approximation of LLM token counts for `max_tokens` budgets.
+//!
+//! Tracks BPE tokenizers on source code closely enough for budgeting without
+//! shipping a vocabulary: identifiers and numbers cost one token per ~4
+//! characters, each punctuation or non-ASCII character costs one, a newline
+//! costs one, and runs of indentation cost one.
+
+const CHARS_PER_WORD_TOKEN: u64 = 4;
+
+/// Estimate the number of tokens in `text`.
+pub fn estimate(text: &str) -> u64 {
+    let mut tokens = 0;
+    let mut word_len = 0;
+    let mut space_run = 0;
+
+    let flush_word = |tokens: &mut u64, word_len: &mut u64| {
+        *tokens += word_len.div_ceil(CHARS_PER_WORD_TOKEN);
+        *word_len = 0;
+    };
+
+    for ch in text.chars() {
+        if ch.is_ascii_alphanumeric() || ch == '_' {
+            word_len += 1;
+            space_run = 0;
+            continue;
+        }
+        flush_word(&mut tokens, &mut word_len);
+
+        match ch {
+            '\n' => {
+                tokens += 1;
+                space_run = 0;
+            }
+            ' ' | '\t' | '\r' => {
+                // A single space is usually merged into the next word;
+                // longer runs (indentation) become one token.
+                space_run += 1;
+                if space_run == 2 {
+                    tokens += 1;
+                }
+            }
+            _ => {
+                tokens += 1;
+                space_run = 0;
+            }
+        }
+    }
+    flush_word(&mut tokens, &mut word_len);
+
+    tokens
+}
+
+/// Byte length of the longest prefix of `text` estimated to fit in
+/// `max_tokens`, preferring to end after a whole line.
+pub fn fit_prefix(text: &str, max_tokens: u64) -> usize {
+    let mut used = 0;
+    let mut len = 0;
+    for line in text.split_inclusive('\n') {
+        let cost = estimate(line);
+        if used + cost > max_tokens {
+            // No whole line fits: cut this one at the longest prefix that
+            // does (only when nothing was taken yet, to always make progress).
+            if len == 0 {
+                let mut ends: Vec<usize> = line.char_indices().map(|(i, _)| i).skip(1).collect();
+                ends.push(line.len());
+                let fits = ends.partition_point(|&end| estimate(&line[..end]) <= max_tokens);
+                return ends[fits.saturating_sub(1)];
+            }
+            return len;
+        }
+        used += cost;
+        len += line.len();
+    }
+    len
+}
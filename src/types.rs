@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use rmcp::schemars;
 use serde::{Deserialize, Serialize};
 
@@ -23,15 +25,49 @@ pub enum RangeType {
     Lines,
 }
 
+/// Raw byte encoding for `read_file` in bytes mode.
+///
+/// - `base64`: standard base64 of the byte range.
+/// - `hex`: lowercase hex digits, two per byte.
+/// - `hexdump`: `hexdump -C` style lines with offsets, hex bytes and ASCII.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ByteEncoding {
+    Base64,
+    Hex,
+    Hexdump,
+}
+
 /// Match mode for `find_files` tool.
 ///
 /// - `name` (default): match only against the file or directory name.
 /// - `path`: match against the full relative path (e.g. "src/main.rs").
+/// - `fuzzy`: fzf-style subsequence match against the relative path (e.g. "bckend"
+///   matches "src/backend.rs"), sorted by score.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, schemars::JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum FindMatchMode {
     Name,
     Path,
+    Fuzzy,
+}
+
+/// Kind of a symbol reported by `file_outline`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SymbolKind {
+    Function,
+    Method,
+    Class,
+    Struct,
+    Enum,
+    Interface,
+    Trait,
+    Impl,
+    Module,
+    Constant,
+    Type,
+    Macro,
 }
 
 /// Arguments for `search_text`.
@@ -64,6 +100,38 @@ pub struct SearchTextArgs {
     #[serde(default)]
     pub exclude_globs: Option<Vec<String>>,
 
+    /// Optional. Only include files of these types (e.g. `["rust", "toml"]`).
+    /// See `list_file_types` for available names.
+    #[serde(default)]
+    pub types: Option<Vec<String>>,
+
+    /// Optional. Exclude files of these types.
+    #[serde(default)]
+    pub exclude_types: Option<Vec<String>>,
+
+    /// Optional. Include hidden files and directories (dotfiles). The `.git`
+    /// directory is always skipped. Default: false.
+    #[serde(default)]
+    pub include_hidden: Option<bool>,
+
+    /// Optional. Ignore all ignore files (`.gitignore`, `.ignore`, `.mcpignore`, ...).
+    /// Default: false.
+    #[serde(default)]
+    pub no_ignore: Option<bool>,
+
+    /// Optional. Ignore only version-control ignore files (`.gitignore`, git excludes),
+    /// still honoring `.ignore` and `.mcpignore`. Default: false.
+    #[serde(default)]
+    pub no_ignore_vcs: Option<bool>,
+
+    /// Optional. Follow symbolic links. Default: false.
+    #[serde(default)]
+    pub follow_symlinks: Option<bool>,
+
+    /// Optional. Maximum directory depth to descend (1 = direct children only).
+    #[serde(default)]
+    pub max_depth: Option<u32>,
+
     /// Optional. Maximum number of hits to return. Default: 200.
     #[serde(default)]
     pub max_results: Option<u32>,
@@ -75,17 +143,42 @@ pub struct SearchTextArgs {
     /// Optional. Number of initial matches to skip (for simple paging). Default: 0.
     #[serde(default)]
     pub skip: Option<u32>,
+
+    /// Optional. Also search binary files (detected by NUL bytes / control
+    /// characters). Matches are reported once per file as `"binary file matches"`
+    /// instead of line text. Default: false (binary files are skipped).
+    #[serde(default)]
+    pub search_binary: Option<bool>,
+
+    /// Optional. Rank hits by relevance (definition-like lines, match density,
+    /// path depth, test vs. source, recency) instead of walk order. `skip` and
+    /// `max_results` then apply to the ranked list. Default: false.
+    #[serde(default)]
+    pub rank: Option<bool>,
+
+    /// Optional. Also search inside compressed files (gzip, zstd, xz, bzip2)
+    /// and zip/tar archives. Hits inside them are reported with paths like
+    /// `"fixtures/data.zip!/inner/file.json"`. Default: false.
+    #[serde(default)]
+    pub search_archives: Option<bool>,
+
+    /// Optional. Stop walking after this many milliseconds and return the
+    /// partial results collected so far with `timed_out: true`.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
 }
 
 /// Arguments for `read_file`.
 #[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
 pub struct ReadFileArgs {
     /// File path relative to server root (e.g. "src/main.rs").
+    /// Archive members are addressed as `"<archive>!/<member>"`
+    /// (e.g. "fixtures/data.zip!/inner/file.json", "logs/app.log.gz!/app.log").
     pub path: String,
 
     /// Optional. `"bytes"` or `"lines"`.
-    /// If omitted, `"lines"` is used when any of `start_line`/`max_lines` is set,
-    /// otherwise `"bytes"` is used.
+    /// If omitted, `"lines"` is used when any of `start_line`/`end_line`/
+    /// `max_lines`/`line_numbers`/`elide` is set, otherwise `"bytes"` is used.
     #[serde(default)]
     pub range_type: Option<RangeType>,
 
@@ -97,13 +190,78 @@ pub struct ReadFileArgs {
     #[serde(default)]
     pub max_bytes: Option<u64>,
 
-    /// Optional. 1-based start line for `"lines"` mode. Default: 1.
+    /// Optional. Return the raw byte range as `"base64"`, `"hex"` or a
+    /// `"hexdump"` view instead of text, so binary files can be inspected.
+    /// Only valid in `"bytes"` mode. Default: text.
     #[serde(default)]
-    pub start_line: Option<u64>,
+    pub encoding: Option<ByteEncoding>,
+
+    /// Optional. When reading an image, downscale it so neither side exceeds
+    /// this many pixels (aspect ratio is kept). Default: no downscaling.
+    #[serde(default)]
+    pub max_image_dimension: Option<u32>,
+
+    /// Optional. 1-based start line for `"lines"` mode. Negative values
+    /// count from the end like `tail` (`-1` = last line, `-20` = the last
+    /// 20 lines). Default: 1.
+    #[serde(default)]
+    pub start_line: Option<i64>,
+
+    /// Optional. Inclusive last line for `"lines"` mode; negative values
+    /// count from the end. `max_lines` still caps the range. Default: none.
+    #[serde(default)]
+    pub end_line: Option<i64>,
 
     /// Optional. Maximum lines to read in `"lines"` mode. Default: 200.
     #[serde(default)]
     pub max_lines: Option<u64>,
+
+    /// Optional. Prefix each line with its 1-based line number and a tab,
+    /// like `cat -n`, in `"lines"` mode. Default: false.
+    #[serde(default)]
+    pub line_numbers: Option<bool>,
+
+    /// Optional. Token budget for the returned content, using a local
+    /// approximation of LLM tokenizers. The largest range from the start
+    /// position that fits is returned; unless `max_lines` / `max_bytes` are
+    /// also given they no longer apply their defaults. Not applied to
+    /// `encoding` (raw byte) reads.
+    #[serde(default)]
+    pub max_tokens: Option<u64>,
+
+    /// Optional. Return an elided view of the whole file in `"lines"` mode:
+    /// the requested `start_line`/`end_line` region, signatures and imports
+    /// are kept while function bodies elsewhere are collapsed into
+    /// `... (lines X-Y elided)` markers. Default: false.
+    #[serde(default)]
+    pub elide: Option<bool>,
+
+    /// Optional. Read exactly one symbol (function, type, ...) as listed by
+    /// `file_outline`, e.g. `"Backend::read_file"` or `"read_file"`. Nested
+    /// names are joined with `::` or `.`; a suffix of the full name is
+    /// enough. Implies `"lines"` mode and cannot be combined with
+    /// `start_line`/`end_line`.
+    #[serde(default)]
+    pub symbol: Option<String>,
+}
+
+/// Arguments for `read_files`.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct ReadFilesArgs {
+    /// Files (or ranges) to read, each with the same fields as `read_file`.
+    /// Images are not returned as media content here.
+    pub files: Vec<ReadFileArgs>,
+
+    /// Optional. Total bytes of content returned across all files. Each
+    /// read is clamped to what is left; once it is used up the remaining
+    /// files are skipped. Default: 256 KiB.
+    #[serde(default)]
+    pub max_total_bytes: Option<u64>,
+
+    /// Optional. Total lines returned across all `"lines"` mode reads.
+    /// Default: unlimited (only `max_total_bytes` applies).
+    #[serde(default)]
+    pub max_total_lines: Option<u64>,
 }
 
 /// Arguments for `list_files`.
@@ -130,6 +288,38 @@ pub struct ListFilesArgs {
     #[serde(default)]
     pub exclude_globs: Option<Vec<String>>,
 
+    /// Optional. Only include files of these types (e.g. `["rust", "toml"]`).
+    /// See `list_file_types` for available names.
+    #[serde(default)]
+    pub types: Option<Vec<String>>,
+
+    /// Optional. Exclude files of these types.
+    #[serde(default)]
+    pub exclude_types: Option<Vec<String>>,
+
+    /// Optional. Include hidden files and directories (dotfiles). The `.git`
+    /// directory is always skipped. Default: false.
+    #[serde(default)]
+    pub include_hidden: Option<bool>,
+
+    /// Optional. Ignore all ignore files (`.gitignore`, `.ignore`, `.mcpignore`, ...).
+    /// Default: false.
+    #[serde(default)]
+    pub no_ignore: Option<bool>,
+
+    /// Optional. Ignore only version-control ignore files (`.gitignore`, git excludes),
+    /// still honoring `.ignore` and `.mcpignore`. Default: false.
+    #[serde(default)]
+    pub no_ignore_vcs: Option<bool>,
+
+    /// Optional. Follow symbolic links. Default: false.
+    #[serde(default)]
+    pub follow_symlinks: Option<bool>,
+
+    /// Optional. Maximum directory depth to descend (1 = direct children only).
+    #[serde(default)]
+    pub max_depth: Option<u32>,
+
     /// Optional. Maximum number of entries to return. Default: 500.
     #[serde(default)]
     pub max_results: Option<u32>,
@@ -145,6 +335,11 @@ pub struct ListFilesArgs {
     /// Optional. Number of matching entries to skip before collecting results. Default: 0.
     #[serde(default)]
     pub skip: Option<u32>,
+
+    /// Optional. Stop walking after this many milliseconds and return the
+    /// partial results collected so far with `timed_out: true`.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
 }
 
 #[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
@@ -155,12 +350,145 @@ pub struct SearchHit {
     pub line_text: String,
     pub context_before: Vec<String>,
     pub context_after: Vec<String>,
+    /// Whether the hit is in a binary file; `line_text` is then a
+    /// placeholder and no context is returned.
+    pub binary: bool,
+    /// Optional. Detected encoding of the file when it is not UTF-8 (e.g.
+    /// `"Shift_JIS"`); `line_text` and context are transcoded to UTF-8.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    /// Optional relevance score (only when `rank` is enabled); higher is better.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+    /// Optional. Last line of a multi-line structural match (1-based).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_line: Option<u64>,
+    /// Optional. Text captured by each metavariable of a structural match.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub captures: Option<BTreeMap<String, String>>,
 }
 
 #[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
 pub struct SearchTextResult {
     pub hits: Vec<SearchHit>,
     pub has_more: bool,
+    /// Whether `timeout_ms` elapsed before the walk finished; results are partial.
+    pub timed_out: bool,
+}
+
+/// Action for `replace_text` tool.
+///
+/// - `preview` (default): compute replacements and return per-file diffs without writing.
+/// - `apply`: write the replaced content back to disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplaceAction {
+    Preview,
+    Apply,
+}
+
+/// Arguments for `replace_text`.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct ReplaceTextArgs {
+    /// Search query string. Literal or regex depending on `mode`.
+    pub query: String,
+
+    /// Replacement text.
+    ///
+    /// - In `"literal"` mode it is inserted as-is.
+    /// - In `"regex"` mode capture groups can be referenced as `$1`, `${1}` or `${name}`.
+    pub replacement: String,
+
+    /// Optional. `"literal"` (default) or `"regex"`.
+    #[serde(default)]
+    pub mode: Option<SearchMode>,
+
+    /// Optional. Case sensitivity for literal/regex search. Default: false.
+    #[serde(default)]
+    pub case_sensitive: Option<bool>,
+
+    /// Optional. Root directory, with the same semantics as `search_text`.
+    #[serde(default)]
+    pub root: Option<String>,
+
+    /// Optional. Only include files matching any of these glob patterns.
+    #[serde(default)]
+    pub include_globs: Option<Vec<String>>,
+
+    /// Optional. Exclude files matching any of these glob patterns.
+    #[serde(default)]
+    pub exclude_globs: Option<Vec<String>>,
+
+    /// Optional. Only include files of these types (e.g. `["rust", "toml"]`).
+    /// See `list_file_types` for available names.
+    #[serde(default)]
+    pub types: Option<Vec<String>>,
+
+    /// Optional. Exclude files of these types.
+    #[serde(default)]
+    pub exclude_types: Option<Vec<String>>,
+
+    /// Optional. Include hidden files and directories (dotfiles). The `.git`
+    /// directory is always skipped. Default: false.
+    #[serde(default)]
+    pub include_hidden: Option<bool>,
+
+    /// Optional. Ignore all ignore files (`.gitignore`, `.ignore`, `.mcpignore`, ...).
+    /// Default: false.
+    #[serde(default)]
+    pub no_ignore: Option<bool>,
+
+    /// Optional. Ignore only version-control ignore files (`.gitignore`, git excludes),
+    /// still honoring `.ignore` and `.mcpignore`. Default: false.
+    #[serde(default)]
+    pub no_ignore_vcs: Option<bool>,
+
+    /// Optional. Follow symbolic links. Default: false.
+    #[serde(default)]
+    pub follow_symlinks: Option<bool>,
+
+    /// Optional. Maximum directory depth to descend (1 = direct children only).
+    #[serde(default)]
+    pub max_depth: Option<u32>,
+
+    /// Optional. `"preview"` (default) or `"apply"`.
+    #[serde(default)]
+    pub action: Option<ReplaceAction>,
+
+    /// Optional. Maximum number of changed files to process. Default: 200.
+    #[serde(default)]
+    pub max_files: Option<u32>,
+
+    /// Optional. Stop walking after this many milliseconds and return a
+    /// preview of the changes found so far with `timed_out: true`; nothing
+    /// is applied.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+}
+
+/// Per-file outcome in `replace_text` result.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct FileReplacement {
+    /// File path, relative to the server root when possible.
+    pub path: String,
+    /// Number of replacements made in this file.
+    pub replacements: u64,
+    /// Optional unified diff of the change (only in `"preview"` action).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct ReplaceTextResult {
+    pub files: Vec<FileReplacement>,
+    /// Total number of replacements across all returned files.
+    pub total_replacements: u64,
+    /// Whether the changes were written to disk.
+    pub applied: bool,
+    pub has_more: bool,
+    /// Whether the walk was stopped by `timeout_ms` before it finished. The
+    /// changes found so far are only previewed, never applied.
+    pub timed_out: bool,
 }
 
 #[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
@@ -169,15 +497,96 @@ pub struct FileRangeInfo {
     pub offset_bytes: Option<u64>,
     pub max_bytes: Option<u64>,
     pub start_line: Option<u64>,
+    /// Optional. Last line returned in `"lines"` mode (1-based, inclusive).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_line: Option<u64>,
     pub max_lines: Option<u64>,
 }
 
+/// Inclusive, 1-based line range.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct LineRange {
+    pub start_line: u64,
+    pub end_line: u64,
+}
+
 #[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
 pub struct FileChunkResult {
     pub path: String,
     pub content: String,
+    /// Whether `max_bytes` / `max_lines` cut the requested range short.
     pub is_truncated: bool,
     pub range: FileRangeInfo,
+    /// Optional. `offset_bytes` for the next chunk in `"bytes"` mode; absent
+    /// at end of file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_offset_bytes: Option<u64>,
+    /// Optional. `start_line` for the next chunk in `"lines"` mode; absent
+    /// at end of file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_start_line: Option<u64>,
+    /// File size in bytes.
+    pub file_size: u64,
+    /// Optional. Estimated token count of `content` (only with `max_tokens`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<u64>,
+    /// Optional. Line ranges collapsed in an `elide` view.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elided_ranges: Option<Vec<LineRange>>,
+    /// Optional. Total number of lines in the file (`"lines"` mode only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_lines: Option<u64>,
+    /// Detected text encoding of the file (e.g. `"UTF-8"`, `"Shift_JIS"`,
+    /// `"UTF-16LE"`). Text `content` is transcoded from it to UTF-8.
+    /// `"UTF-8 (lossy)"` means invalid bytes in the returned range were
+    /// replaced with U+FFFD.
+    pub encoding: String,
+    /// Optional. The requested byte encoding of `content` (`"base64"`,
+    /// `"hex"`, `"hexdump"`) when raw bytes were read.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub byte_encoding: Option<ByteEncoding>,
+}
+
+/// Result for one entry of `read_files`: either the chunk or the error.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct ReadFilesItem {
+    /// Path as given in the request.
+    pub path: String,
+    /// Optional. The chunk read, as returned by `read_file`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunk: Option<FileChunkResult>,
+    /// Optional. Why this entry could not be read; other entries are unaffected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct ReadFilesResult {
+    /// One item per requested file, in request order.
+    pub files: Vec<ReadFilesItem>,
+    /// Total bytes of content returned.
+    pub total_bytes: u64,
+    /// Whether the byte or line budget ran out before every file was read in full.
+    pub budget_exhausted: bool,
+}
+
+/// Metadata returned next to the image / embedded resource content when
+/// `read_file` reads an image or PDF.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct MediaInfo {
+    pub path: String,
+    /// Mime type detected from the file's magic bytes (e.g. `"image/png"`).
+    pub mime_type: String,
+    /// Size in bytes of the returned data.
+    pub size: u64,
+    /// Optional. Image width in pixels, after any downscaling.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    /// Optional. Image height in pixels, after any downscaling.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    /// Whether the image was downscaled to fit `max_image_dimension`.
+    pub downscaled: bool,
 }
 
 /// A single entry in `list_files` result.
@@ -202,6 +611,8 @@ pub struct FileEntry {
 pub struct ListFilesResult {
     pub entries: Vec<FileEntry>,
     pub has_more: bool,
+    /// Whether `timeout_ms` elapsed before the walk finished; results are partial.
+    pub timed_out: bool,
 }
 
 /// Arguments for `find_files`.
@@ -230,7 +641,39 @@ pub struct FindFilesArgs {
     #[serde(default)]
     pub exclude_globs: Option<Vec<String>>,
 
-    /// Optional. Match mode: `\"name\"` (default) or `\"path\"`.
+    /// Optional. Only include files of these types (e.g. `["rust", "toml"]`).
+    /// See `list_file_types` for available names.
+    #[serde(default)]
+    pub types: Option<Vec<String>>,
+
+    /// Optional. Exclude files of these types.
+    #[serde(default)]
+    pub exclude_types: Option<Vec<String>>,
+
+    /// Optional. Include hidden files and directories (dotfiles). The `.git`
+    /// directory is always skipped. Default: false.
+    #[serde(default)]
+    pub include_hidden: Option<bool>,
+
+    /// Optional. Ignore all ignore files (`.gitignore`, `.ignore`, `.mcpignore`, ...).
+    /// Default: false.
+    #[serde(default)]
+    pub no_ignore: Option<bool>,
+
+    /// Optional. Ignore only version-control ignore files (`.gitignore`, git excludes),
+    /// still honoring `.ignore` and `.mcpignore`. Default: false.
+    #[serde(default)]
+    pub no_ignore_vcs: Option<bool>,
+
+    /// Optional. Follow symbolic links. Default: false.
+    #[serde(default)]
+    pub follow_symlinks: Option<bool>,
+
+    /// Optional. Maximum directory depth to descend (1 = direct children only).
+    #[serde(default)]
+    pub max_depth: Option<u32>,
+
+    /// Optional. Match mode: `\"name\"` (default), `\"path\"` or `\"fuzzy\"`.
     #[serde(default)]
     pub match_mode: Option<FindMatchMode>,
 
@@ -249,6 +692,11 @@ pub struct FindFilesArgs {
     /// Optional. Number of matching entries to skip before collecting results. Default: 0.
     #[serde(default)]
     pub skip: Option<u32>,
+
+    /// Optional. Stop walking after this many milliseconds and return the
+    /// partial results collected so far with `timed_out: true`.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
 }
 
 /// A single match in `find_files` result.
@@ -261,12 +709,21 @@ pub struct FindFileMatch {
     pub path: String,
     /// Whether this match is a directory.
     pub is_dir: bool,
+    /// Optional fuzzy match score (only in `"fuzzy"` mode); higher is better.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<i64>,
+    /// Optional character indices into `path` matched by the query
+    /// (only in `"fuzzy"` mode).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub positions: Option<Vec<u32>>,
 }
 
 #[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
 pub struct FindFilesResult {
     pub matches: Vec<FindFileMatch>,
     pub has_more: bool,
+    /// Whether `timeout_ms` elapsed before the walk finished; results are partial.
+    pub timed_out: bool,
 }
 
 /// Arguments for `stat`.
@@ -357,6 +814,17 @@ pub struct CreateFileArgs {
     /// Optional. Create parent directories as needed. Default: false.
     #[serde(default)]
     pub create_parents: Option<bool>,
+
+    /// Optional. Format the content with the project's formatter before
+    /// writing. Default: the server config's `format_on_write.enabled`.
+    #[serde(default)]
+    pub format: Option<bool>,
+
+    /// Optional. Parse the content (after formatting) and refuse the write
+    /// if it has syntax errors. Supports the tree-sitter languages, JSON,
+    /// TOML and YAML; other files are written unchecked. Default: false.
+    #[serde(default)]
+    pub validate_syntax: Option<bool>,
 }
 
 /// Result for `create_file`.
@@ -368,6 +836,26 @@ pub struct CreateFileResult {
     pub created: bool,
     /// Whether an existing file was overwritten.
     pub overwritten: bool,
+
+    /// Formatting outcome; omitted when formatting was not requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formatting: Option<FormatReport>,
+}
+
+/// Outcome of formatting content before a write.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct FormatReport {
+    /// Formatter that was run, e.g. `"rustfmt"`. Omitted when the file has
+    /// none.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formatter: Option<String>,
+    /// Whether formatting changed the content.
+    pub changed: bool,
+    /// Why the content was written unformatted: no formatter for the file,
+    /// the formatter is not installed, timed out or rejected the content
+    /// (e.g. a syntax error).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<String>,
 }
 
 /// Arguments for `delete_path`.
@@ -485,6 +973,17 @@ pub struct OverwriteFileArgs {
 
     /// New content for the file. Existing content is fully replaced.
     pub content: String,
+
+    /// Optional. Format the content with the project's formatter before
+    /// writing. Default: the server config's `format_on_write.enabled`.
+    #[serde(default)]
+    pub format: Option<bool>,
+
+    /// Optional. Parse the content (after formatting) and refuse the write
+    /// if it has syntax errors. Supports the tree-sitter languages, JSON,
+    /// TOML and YAML; other files are written unchecked. Default: false.
+    #[serde(default)]
+    pub validate_syntax: Option<bool>,
 }
 
 /// Result for `overwrite_file`.
@@ -492,4 +991,610 @@ pub struct OverwriteFileArgs {
 pub struct OverwriteFileResult {
     /// Final path of the overwritten file (relative to server root when possible).
     pub path: String,
+
+    /// Formatting outcome; omitted when formatting was not requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formatting: Option<FormatReport>,
+}
+
+/// Arguments for `list_file_types`.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct ListFileTypesArgs {
+    /// Optional. Only return types whose name contains this substring (case-insensitive).
+    #[serde(default)]
+    pub filter: Option<String>,
+}
+
+/// A single file type definition in `list_file_types` result.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct FileTypeInfo {
+    /// Type name usable in `types` / `exclude_types`.
+    pub name: String,
+    /// Globs matched by this type.
+    pub globs: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct ListFileTypesResult {
+    pub types: Vec<FileTypeInfo>,
+}
+
+/// Arguments for `file_outline`.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct FileOutlineArgs {
+    /// File path relative to the server root.
+    pub path: String,
+
+    /// Optional. Maximum nesting depth of returned symbols (1 = top-level
+    /// only). Default: unlimited.
+    #[serde(default)]
+    pub max_depth: Option<u32>,
+}
+
+/// A symbol in a file outline.
+#[derive(Debug, Clone, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct OutlineSymbol {
+    pub kind: SymbolKind,
+    pub name: String,
+    /// 1-based first line (including Python decorators).
+    pub start_line: u64,
+    /// 1-based inclusive last line.
+    pub end_line: u64,
+    /// Declaration up to its body, whitespace collapsed (e.g.
+    /// `pub fn read_file(&self, args: ReadFileArgs) -> Result<FileChunkResult>`).
+    pub signature: String,
+    /// Nested symbols (methods of a class, items of a module, ...).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<OutlineSymbol>,
+}
+
+/// Result for `file_outline`.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct FileOutlineResult {
+    pub path: String,
+    /// Language used to parse the file (e.g. `"rust"`, `"typescript"`).
+    pub language: String,
+    pub symbols: Vec<OutlineSymbol>,
+}
+
+/// Name matching mode for `search_symbols`.
+///
+/// - `substring` (default): the symbol name contains the query.
+/// - `exact`: the symbol name equals the query.
+/// - `fuzzy`: fzf-style subsequence match (e.g. "rdfl" matches "read_file"),
+///   sorted by score.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SymbolMatchMode {
+    Substring,
+    Exact,
+    Fuzzy,
+}
+
+/// Arguments for `search_symbols`.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct SearchSymbolsArgs {
+    /// Symbol name or query. A query containing `::` or `.` (e.g.
+    /// `"Backend::read_file"`) is matched against the qualified name
+    /// (container plus name) instead of the bare name.
+    pub query: String,
+
+    /// Optional. `"substring"` (default), `"exact"` or `"fuzzy"`.
+    #[serde(default)]
+    pub mode: Option<SymbolMatchMode>,
+
+    /// Optional. Only return symbols of these kinds (e.g. `["function", "method"]`).
+    #[serde(default)]
+    pub kinds: Option<Vec<SymbolKind>>,
+
+    /// Optional. Case sensitivity for name matching. Default: false.
+    #[serde(default)]
+    pub case_sensitive: Option<bool>,
+
+    /// Optional. Root directory, resolved like `search_text`'s `root`.
+    #[serde(default)]
+    pub root: Option<String>,
+
+    /// Optional. Only include files matching any of these glob patterns.
+    #[serde(default)]
+    pub include_globs: Option<Vec<String>>,
+
+    /// Optional. Exclude files matching any of these glob patterns.
+    #[serde(default)]
+    pub exclude_globs: Option<Vec<String>>,
+
+    /// Optional. Only include files of these types (e.g. `["rust"]`).
+    /// See `list_file_types` for available names.
+    #[serde(default)]
+    pub types: Option<Vec<String>>,
+
+    /// Optional. Exclude files of these types.
+    #[serde(default)]
+    pub exclude_types: Option<Vec<String>>,
+
+    /// Optional. Include hidden files and directories (dotfiles). Default: false.
+    #[serde(default)]
+    pub include_hidden: Option<bool>,
+
+    /// Optional. Ignore all ignore files (`.gitignore`, `.ignore`, `.mcpignore`, ...).
+    /// Default: false.
+    #[serde(default)]
+    pub no_ignore: Option<bool>,
+
+    /// Optional. Ignore only version-control ignore files. Default: false.
+    #[serde(default)]
+    pub no_ignore_vcs: Option<bool>,
+
+    /// Optional. Follow symbolic links. Default: false.
+    #[serde(default)]
+    pub follow_symlinks: Option<bool>,
+
+    /// Optional. Maximum directory depth to descend (1 = direct children only).
+    #[serde(default)]
+    pub max_depth: Option<u32>,
+
+    /// Optional. Maximum number of symbols to return. Default: 100.
+    #[serde(default)]
+    pub max_results: Option<u32>,
+
+    /// Optional. Number of initial results to skip (for simple paging). Default: 0.
+    #[serde(default)]
+    pub skip: Option<u32>,
+
+    /// Optional. Stop walking after this many milliseconds and return the
+    /// partial results collected so far with `timed_out: true`.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+}
+
+/// A symbol definition found by `search_symbols`.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct SymbolMatch {
+    /// File path relative to the server root.
+    pub path: String,
+    pub name: String,
+    pub kind: SymbolKind,
+    pub start_line: u64,
+    pub end_line: u64,
+    /// Optional. Qualified name of the enclosing symbol (e.g. the class or
+    /// impl of a method).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+    pub signature: String,
+    /// Optional. Fuzzy match score (only for `"fuzzy"` mode; higher is better).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<i64>,
+}
+
+/// Result for `search_symbols`.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct SearchSymbolsResult {
+    pub symbols: Vec<SymbolMatch>,
+    pub has_more: bool,
+    /// Whether `timeout_ms` elapsed before the walk finished; results are partial.
+    pub timed_out: bool,
+}
+
+/// Arguments for `search_structural`.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct SearchStructuralArgs {
+    /// Code pattern with metavariables, e.g. `"$X.unwrap()"`. `$NAME` matches
+    /// one syntax node, `$$$NAME` a (possibly empty) sequence of nodes such as
+    /// call arguments, and `$_` matches anything without capturing.
+    pub pattern: String,
+
+    /// Optional. Only search files of this language (`"rust"`, `"typescript"`,
+    /// `"tsx"`, `"javascript"`, `"python"`, `"go"`, `"c"`, `"cpp"`, `"java"`).
+    /// Default: every supported language the pattern parses in.
+    #[serde(default)]
+    pub language: Option<String>,
+
+    /// Optional. Rewrite template for structural replace, e.g.
+    /// `"$X.expect(\"TODO\")"`. Metavariables are replaced by their captures.
+    #[serde(default)]
+    pub rewrite: Option<String>,
+
+    /// Optional. With `rewrite`: `"preview"` (default) returns diffs,
+    /// `"apply"` writes the changes.
+    #[serde(default)]
+    pub action: Option<ReplaceAction>,
+
+    /// Optional. Root directory, resolved like `search_text`'s `root`.
+    #[serde(default)]
+    pub root: Option<String>,
+
+    /// Optional. Only include files matching any of these glob patterns.
+    #[serde(default)]
+    pub include_globs: Option<Vec<String>>,
+
+    /// Optional. Exclude files matching any of these glob patterns.
+    #[serde(default)]
+    pub exclude_globs: Option<Vec<String>>,
+
+    /// Optional. Include hidden files and directories (dotfiles). Default: false.
+    #[serde(default)]
+    pub include_hidden: Option<bool>,
+
+    /// Optional. Ignore all ignore files (`.gitignore`, `.ignore`, `.mcpignore`, ...).
+    /// Default: false.
+    #[serde(default)]
+    pub no_ignore: Option<bool>,
+
+    /// Optional. Follow symbolic links. Default: false.
+    #[serde(default)]
+    pub follow_symlinks: Option<bool>,
+
+    /// Optional. Maximum directory depth to descend (1 = direct children only).
+    #[serde(default)]
+    pub max_depth: Option<u32>,
+
+    /// Optional. Maximum number of hits to return. Default: 200.
+    #[serde(default)]
+    pub max_results: Option<u32>,
+
+    /// Optional. Number of context lines before/after each hit. Default: 2.
+    #[serde(default)]
+    pub context_lines: Option<u32>,
+
+    /// Optional. Maximum number of files to rewrite. Default: 200.
+    #[serde(default)]
+    pub max_files: Option<u32>,
+
+    /// Optional. Stop walking after this many milliseconds and return the
+    /// partial results collected so far with `timed_out: true`.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+}
+
+/// Result for `search_structural`.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct SearchStructuralResult {
+    /// Matches, with `end_line` and `captures` set.
+    pub hits: Vec<SearchHit>,
+    pub has_more: bool,
+    /// Whether `timeout_ms` elapsed before the walk finished; results are partial.
+    pub timed_out: bool,
+    /// Optional. Per-file rewrites (only with `rewrite`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rewrite: Option<ReplaceTextResult>,
+}
+
+/// A position in a file, as `search_text` reports hits.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct LspPositionArgs {
+    /// File path relative to the server root (or absolute inside it).
+    pub path: String,
+
+    /// Line number (1-based).
+    pub line: u64,
+
+    /// Optional. Byte offset within the line (0-based), like `search_text`'s
+    /// `column`. Default: the first non-whitespace character.
+    #[serde(default)]
+    pub column: Option<u64>,
+}
+
+/// Arguments for `find_references`.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct FindReferencesArgs {
+    /// File path relative to the server root (or absolute inside it).
+    pub path: String,
+
+    /// Line number (1-based).
+    pub line: u64,
+
+    /// Optional. Byte offset within the line (0-based), like `search_text`'s
+    /// `column`. Default: the first non-whitespace character.
+    #[serde(default)]
+    pub column: Option<u64>,
+
+    /// Optional. Include the declaration itself. Default: true.
+    #[serde(default)]
+    pub include_declaration: Option<bool>,
+
+    /// Optional. Maximum number of locations to return. Default: 200.
+    #[serde(default)]
+    pub max_results: Option<u32>,
+}
+
+/// A source range reported by a language server.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct LspLocation {
+    /// Path relative to the server root, or absolute for files outside it
+    /// (e.g. library sources).
+    pub path: String,
+    /// Line number (1-based).
+    pub line: u64,
+    /// Byte offset within the line (0-based).
+    pub column: u64,
+    pub end_line: u64,
+    pub end_column: u64,
+    /// Text of the first line of the range (empty if the file is unreadable).
+    pub line_text: String,
+}
+
+/// Result for `goto_definition` and `find_references`.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct LspLocationsResult {
+    pub locations: Vec<LspLocation>,
+    pub has_more: bool,
+}
+
+/// Result for `hover`.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct HoverResult {
+    /// Hover text (usually Markdown: type signature and docs), or `null` when
+    /// the server has nothing for this position.
+    pub contents: Option<String>,
+}
+
+/// Arguments for `diagnostics`.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct DiagnosticsArgs {
+    /// Optional. File to get language server diagnostics for. With `output`,
+    /// only the diagnostics for this file are returned. Required unless
+    /// `output` is given.
+    #[serde(default)]
+    pub path: Option<String>,
+
+    /// Optional. Milliseconds to wait for the server to publish diagnostics
+    /// after the file was opened or changed. Default: 5000.
+    #[serde(default)]
+    pub wait_ms: Option<u64>,
+
+    /// Optional. Compiler or linter output to parse instead of asking a
+    /// language server, e.g. the `stdout` of `run_command`.
+    #[serde(default)]
+    pub output: Option<String>,
+
+    /// Optional. Format of `output`. Default: "auto".
+    #[serde(default)]
+    pub format: Option<DiagnosticsFormat>,
+
+    /// Optional. Directory the command ran in, relative to the root; relative
+    /// paths in `output` are resolved against it. Default: the root.
+    #[serde(default)]
+    pub cwd: Option<String>,
+}
+
+/// Format of the `output` passed to `diagnostics`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DiagnosticsFormat {
+    /// Detect the format line by line.
+    Auto,
+    /// `cargo --message-format=json` or `rustc --error-format=json`.
+    Cargo,
+    /// `tsc`, with or without `--pretty`.
+    Tsc,
+    /// `eslint --format json`.
+    Eslint,
+    /// `path:line:col: severity: message` lines (gcc, clang, go, mypy, javac, ...).
+    Gcc,
+}
+
+impl DiagnosticsFormat {
+    /// The name used for this format in arguments and error messages.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Cargo => "cargo",
+            Self::Tsc => "tsc",
+            Self::Eslint => "eslint",
+            Self::Gcc => "gcc",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DiagnosticSeverity {
+    Error,
+    Warning,
+    Info,
+    Hint,
+}
+
+/// One error, warning or hint.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct Diagnostic {
+    /// Path relative to the server root, or absolute for files outside it.
+    pub path: String,
+    /// Line number (1-based).
+    pub line: u64,
+    /// Byte offset within the line (0-based).
+    pub column: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_line: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_column: Option<u64>,
+    pub severity: DiagnosticSeverity,
+    /// Optional. Error code, e.g. `"E0308"` or `"TS2322"`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    pub message: String,
+    /// Optional. Tool that reported it, e.g. `"rustc"` or `"Pyright"`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+/// Result for `diagnostics`.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct DiagnosticsResult {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Arguments for `rename_symbol`.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct RenameSymbolArgs {
+    /// File containing an occurrence of the symbol.
+    pub path: String,
+
+    /// Line of the occurrence (1-based).
+    pub line: u64,
+
+    /// Byte offset of the occurrence within the line (0-based), like
+    /// `search_text`'s `column`. Any byte inside the name works.
+    pub column: u64,
+
+    /// New name for the symbol: letters, digits and `_`, not starting with a
+    /// digit (`$` is also allowed in JavaScript and TypeScript).
+    pub new_name: String,
+
+    /// Optional. `"preview"` (default) returns diffs, `"apply"` writes the changes.
+    #[serde(default)]
+    pub action: Option<ReplaceAction>,
+}
+
+/// Result for `rename_symbol`.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct RenameSymbolResult {
+    pub old_name: String,
+    pub new_name: String,
+    /// Per-file changes; `diff` is set when previewing.
+    pub files: Vec<FileReplacement>,
+    pub total_replacements: u64,
+    pub applied: bool,
+}
+
+/// Arguments for `run_command`.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct RunCommandArgs {
+    /// Program and arguments, e.g. `["cargo", "test", "--", "parser"]`. Run
+    /// directly, without a shell; must start with an allowed command.
+    pub command: Vec<String>,
+
+    /// Optional. Working directory relative to the server root. Default: the root.
+    #[serde(default)]
+    pub cwd: Option<String>,
+
+    /// Optional. Kill the command after this many milliseconds.
+    /// Default: 120000 (capped by the server's `max_timeout_ms`).
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+
+    /// Optional. Bytes of stdout and of stderr to return; the start and end
+    /// are kept and the middle dropped. Default: 65536 (capped by the
+    /// server's `max_output_bytes`).
+    #[serde(default)]
+    pub max_output_bytes: Option<u64>,
+
+    /// Optional. Run without network access (Linux only). Cannot turn off a
+    /// server-wide `deny_network`. Default: false.
+    #[serde(default)]
+    pub deny_network: Option<bool>,
+}
+
+/// Result for `run_command`.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct RunCommandResult {
+    /// Exit code, or `null` if the command was killed by a signal.
+    pub exit_code: Option<i32>,
+    /// Whether the command exited with status 0 within the timeout.
+    pub success: bool,
+    pub timed_out: bool,
+    pub duration_ms: u64,
+    pub stdout: String,
+    pub stderr: String,
+    /// Whether output was dropped from the middle of stdout.
+    pub stdout_truncated: bool,
+    /// Whether output was dropped from the middle of stderr.
+    pub stderr_truncated: bool,
+}
+
+/// Arguments for `repo_stats`.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct RepoStatsArgs {
+    /// Optional. Root directory, resolved like `search_text`'s `root`.
+    #[serde(default)]
+    pub root: Option<String>,
+
+    /// Optional. Only count files matching any of these glob patterns.
+    #[serde(default)]
+    pub include_globs: Option<Vec<String>>,
+
+    /// Optional. Skip files and directories matching any of these glob patterns.
+    #[serde(default)]
+    pub exclude_globs: Option<Vec<String>>,
+
+    /// Optional. Only count files of these types (e.g. `["rust", "toml"]`).
+    /// See `list_file_types` for available names.
+    #[serde(default)]
+    pub types: Option<Vec<String>>,
+
+    /// Optional. Skip files of these types.
+    #[serde(default)]
+    pub exclude_types: Option<Vec<String>>,
+
+    /// Optional. Include hidden files and directories (dotfiles). Default: false.
+    #[serde(default)]
+    pub include_hidden: Option<bool>,
+
+    /// Optional. Ignore all ignore files (`.gitignore`, `.ignore`, `.mcpignore`, ...).
+    /// Default: false.
+    #[serde(default)]
+    pub no_ignore: Option<bool>,
+
+    /// Optional. Follow symbolic links. Default: false.
+    #[serde(default)]
+    pub follow_symlinks: Option<bool>,
+
+    /// Optional. Maximum directory depth to descend (1 = direct children only).
+    #[serde(default)]
+    pub max_depth: Option<u32>,
+
+    /// Optional. Number of largest files and deepest directories to return.
+    /// Default: 10.
+    #[serde(default)]
+    pub top: Option<u32>,
+
+    /// Optional. Stop walking after this many milliseconds and return the
+    /// statistics collected so far with `timed_out: true`.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+}
+
+/// File and line counts for one language (or all of them).
+#[derive(Debug, Default, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct LanguageStats {
+    /// Language name, e.g. `"Rust"`; `"Other"` for files whose lines are not
+    /// counted (unknown types and binary files); `"Total"` for the sum.
+    pub language: String,
+    pub files: u64,
+    /// Lines containing code (including code followed by a comment).
+    pub code: u64,
+    /// Lines containing only comments.
+    pub comment: u64,
+    pub blank: u64,
+    pub bytes: u64,
+}
+
+/// A file in `repo_stats`' largest files.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct FileSize {
+    pub path: String,
+    pub bytes: u64,
+    pub language: String,
+}
+
+/// A directory in `repo_stats`' deepest directories.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct DirectoryDepth {
+    pub path: String,
+    /// Number of path components below the walk root (1 = direct child).
+    pub depth: u64,
+}
+
+/// Result for `repo_stats`.
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct RepoStatsResult {
+    /// Per-language statistics, most lines of code first.
+    pub languages: Vec<LanguageStats>,
+    pub total: LanguageStats,
+    /// Largest files, biggest first.
+    pub largest_files: Vec<FileSize>,
+    /// Deepest directories, deepest first.
+    pub deepest_directories: Vec<DirectoryDepth>,
+    /// Whether `timeout_ms` elapsed before the walk finished; results are partial.
+    pub timed_out: bool,
 }
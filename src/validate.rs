@@ -0,0 +1,211 @@
+//! Syntax checks for content about to be written: tree-sitter for the
+//! bundled languages, serde parsers for JSON, TOML and YAML.
+
+use std::fmt;
+use std::path::Path;
+
+use serde::Deserialize;
+use serde::de::IgnoredAny;
+
+use crate::syntax::Language;
+
+/// Errors reported per file; tree-sitter recovers and keeps finding more.
+const MAX_ISSUES: usize = 5;
+/// Characters of an unexpected token quoted in a message.
+const MAX_SNIPPET_CHARS: usize = 30;
+/// `.json` files whose readers accept comments and trailing commas.
+const JSONC_FILE_NAMES: &[&str] = &[
+    ".babelrc.json",
+    ".devcontainer.json",
+    ".eslintrc.json",
+    "api-extractor.json",
+    "biome.json",
+    "deno.json",
+    "devcontainer.json",
+    "language-configuration.json",
+    "tslint.json",
+    "turbo.json",
+    "typedoc.json",
+];
+/// Directories whose `.json` files are all read as JSONC.
+const JSONC_DIRS: &[&str] = &[".vscode", ".devcontainer", ".zed"];
+
+/// Where and why content does not parse.
+#[derive(Debug)]
+pub struct SyntaxIssue {
+    /// 1-based.
+    pub line: u64,
+    /// 0-based byte offset within the line.
+    pub column: u64,
+    pub message: String,
+}
+
+/// Content that does not parse as the language of its file.
+#[derive(Debug)]
+pub struct InvalidSyntax {
+    pub language: &'static str,
+    pub issues: Vec<SyntaxIssue>,
+}
+
+impl fmt::Display for InvalidSyntax {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, issue) in self.issues.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(
+                f,
+                "line {}, column {}: {}",
+                issue.line, issue.column, issue.message
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Check that `content` parses as the language of `path`. Files of other
+/// types always pass.
+pub fn check(path: &Path, content: &str) -> Result<(), InvalidSyntax> {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    let (language, issues) = match extension.as_str() {
+        // These JSON files are read by tools that allow comments.
+        "json" if allows_comments(path, &file_name) => return Ok(()),
+        "json" => ("json", json(content)),
+        "toml" => ("toml", toml(content)),
+        "yaml" | "yml" => ("yaml", yaml(content)),
+        _ => match Language::for_path(path) {
+            Some(language) => (language.name(), tree_sitter(language, content)),
+            None => return Ok(()),
+        },
+    };
+    if issues.is_empty() {
+        Ok(())
+    } else {
+        Err(InvalidSyntax { language, issues })
+    }
+}
+
+fn allows_comments(path: &Path, file_name: &str) -> bool {
+    file_name.starts_with("tsconfig")
+        || file_name.starts_with("jsconfig")
+        || JSONC_FILE_NAMES.contains(&file_name)
+        || path
+            .parent()
+            .and_then(Path::file_name)
+            .is_some_and(|dir| JSONC_DIRS.iter().any(|name| dir == *name))
+}
+
+fn tree_sitter(language: Language, content: &str) -> Vec<SyntaxIssue> {
+    let Some(tree) = language.parse(content) else {
+        return Vec::new();
+    };
+    let mut issues = Vec::new();
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+        if issues.len() >= MAX_ISSUES {
+            break;
+        }
+        let position = node.start_position();
+        let issue = |message: String| SyntaxIssue {
+            line: position.row as u64 + 1,
+            column: position.column as u64,
+            message,
+        };
+        if node.is_missing() {
+            issues.push(issue(format!("missing `{}`", node.kind())));
+        } else if node.is_error() {
+            let text = &content[node.byte_range()];
+            let snippet: String = text
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .chars()
+                .take(MAX_SNIPPET_CHARS)
+                .collect();
+            issues.push(issue(if snippet.trim().is_empty() {
+                "syntax error".to_string()
+            } else {
+                format!("unexpected `{}`", snippet.trim())
+            }));
+        } else if node.has_error() {
+            // Push in reverse so issues come out in document order.
+            let mut cursor = node.walk();
+            let children: Vec<_> = node.children(&mut cursor).collect();
+            stack.extend(children.into_iter().rev());
+        }
+    }
+    issues
+}
+
+fn json(content: &str) -> Vec<SyntaxIssue> {
+    match serde_json::from_str::<IgnoredAny>(content) {
+        Ok(_) => Vec::new(),
+        Err(err) => {
+            // serde_json appends " at line L column C" to the message.
+            let message = err.to_string();
+            let message = message
+                .rsplit_once(" at line ")
+                .map_or(message.as_str(), |(message, _)| message);
+            vec![SyntaxIssue {
+                line: err.line() as u64,
+                column: err.column().saturating_sub(1) as u64,
+                message: message.to_string(),
+            }]
+        }
+    }
+}
+
+fn toml(content: &str) -> Vec<SyntaxIssue> {
+    match content.parse::<toml::Table>() {
+        Ok(_) => Vec::new(),
+        Err(err) => {
+            let offset = err.span().map_or(0, |span| span.start);
+            let (line, column) = line_column(content, offset);
+            vec![SyntaxIssue {
+                line,
+                column,
+                message: err.message().trim().to_string(),
+            }]
+        }
+    }
+}
+
+fn yaml(content: &str) -> Vec<SyntaxIssue> {
+    // Files may hold several `---` separated documents.
+    for document in serde_yaml::Deserializer::from_str(content) {
+        if let Err(err) = IgnoredAny::deserialize(document) {
+            let (line, column) = err
+                .location()
+                .map_or((1, 0), |at| line_column(content, at.index()));
+            // serde_yaml appends " at line L column C" as well.
+            let message = err.to_string();
+            let message = message
+                .split_once(" at line ")
+                .map_or(message.as_str(), |(message, _)| message);
+            return vec![SyntaxIssue {
+                line,
+                column,
+                message: message.to_string(),
+            }];
+        }
+    }
+    Vec::new()
+}
+
+/// 1-based line and 0-based byte column of byte `offset` in `content`.
+fn line_column(content: &str, offset: usize) -> (u64, u64) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() as u64 + 1;
+    let column = before
+        .rfind('\n')
+        .map_or(before.len(), |newline| before.len() - newline - 1);
+    (line, column as u64)
+}